@@ -0,0 +1,108 @@
+/*!
+ * Key setup throughput of `new`, `new_boxed` and `set_key`, run with `cargo bench --bench key_setup`
+ *
+ * The last two columns time only the inverse S-Boxes of a key setup, built
+ * the way `s_box_inv_gen` did before `S0_INV` existed, by inverting
+ * `S0 ^ digest` with `s_inv_gen`, and the way it does now, by reading
+ * `S0_INV[y ^ digest]`. Both run in the same process, as timings of
+ * separate builds differ more between runs than these two do.
+ */
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use narrowway::rng::SplitMix64;
+use narrowway::{Cipher256, Cipher384, Cipher512, S0, S0_INV};
+
+const KEYS: usize = 256;
+const PASSES: usize = 20;
+
+/** Key setups per second of `setup` over `keys`, the best of `PASSES` passes to ignore other load */
+fn throughput<K: Copy>(keys: &[K], mut setup: impl FnMut(K)) -> f64 {
+    (0..PASSES)
+        .map(|_| {
+            let start = Instant::now();
+            for &key in keys {
+                setup(black_box(key));
+            }
+            keys.len() as f64 / start.elapsed().as_secs_f64()
+        })
+        .fold(0.0, f64::max)
+}
+
+/** Inverse S-Box of `S0 ^ digest` through `s_inv_gen` */
+fn invert(digest: u8) -> [u8; 256] {
+    let s_box = S0.map(|byte| byte ^ digest);
+    let mut s_inv = [0; 256];
+    for i in 0..256 {
+        s_inv[s_box[i] as usize] = i as u8;
+    }
+    s_inv
+}
+
+/** Inverse S-Box of `S0 ^ digest` read from `S0_INV` */
+fn lookup(digest: u8) -> [u8; 256] {
+    let mut s_inv = [0; 256];
+    for (i, byte) in s_inv.iter_mut().enumerate() {
+        *byte = S0_INV[i ^ digest as usize];
+    }
+    s_inv
+}
+
+macro_rules! bench {
+    ($cipher: ident, $size: expr) => {{
+        let mut rng = SplitMix64::new($size);
+        let keys: Vec<[u8; $size]> = (0..KEYS)
+            .map(|_| {
+                let mut key = [0; $size];
+                rng.fill_bytes(&mut key);
+                key
+            })
+            .collect();
+
+        let new = throughput(&keys, |key| {
+            black_box($cipher::new(key));
+        });
+        let boxed = throughput(&keys, |key| {
+            black_box($cipher::new_boxed(key));
+        });
+        let mut cipher = $cipher::new_boxed(keys[0]);
+        let set_key = throughput(&keys, |key| {
+            cipher.set_key(key);
+            black_box(&cipher);
+        });
+
+        let digests: Vec<_> = keys
+            .iter()
+            .map(|&key| *$cipher::new(key).digests())
+            .collect();
+        let inverted = throughput(&digests, |digests| {
+            for &digest in digests.as_flattened() {
+                black_box(invert(black_box(digest)));
+            }
+        });
+        let looked_up = throughput(&digests, |digests| {
+            for &digest in digests.as_flattened() {
+                black_box(lookup(black_box(digest)));
+            }
+        });
+
+        println!(
+            "NarrowWay-{:<3}  {:>8.0}  {:>9.0}  {:>8.0}  {:>10.2}x  {:>9.0}  {:>8.0}",
+            $size * 8,
+            new,
+            boxed,
+            set_key,
+            set_key / new,
+            inverted,
+            looked_up
+        );
+    }};
+}
+
+fn main() {
+    println!("keys/s              new  new_boxed   set_key  set_key/new  s_inv_gen    S0_INV");
+    bench!(Cipher256, 32);
+    bench!(Cipher384, 48);
+    bench!(Cipher512, 64);
+}
@@ -94,6 +94,11 @@ fn main() -> std::io::Result<()> {
         *byte = bit_transform(gf_mul_inv(i as u8, GF28_M));
     }
 
+    let mut s0_inv = [0; 256];
+    for (i, byte) in s0.iter().enumerate() {
+        s0_inv[*byte as usize] = i as u8;
+    }
+
     let mut gf28_inv = [0; 256];
     for (i, byte) in gf28_inv.iter_mut().enumerate() {
         *byte = gf_mul_inv(i as u8, GF28_M);
@@ -106,20 +111,36 @@ fn main() -> std::io::Result<()> {
         }
     }
 
-    let mut rc_lis = [0; 32];
+    /* 2 generates the multiplicative group, GF28_LOG[0] is never read */
+    let mut gf28_log = [0; 256];
+    let mut gf28_exp = [0; 256];
+    let mut power = 1;
+    for (i, exp) in gf28_exp.iter_mut().take(255).enumerate() {
+        *exp = power;
+        gf28_log[power as usize] = i as u8;
+        power = gf_mul(power, 2, GF28_M);
+    }
+    gf28_exp[255] = gf28_exp[0];
+
+    let mut rc_lis = [0; 64];
     for (i, v) in rc_lis.iter_mut().enumerate() {
         *v = rc(i);
     }
 
     let mut f = File::create(path).unwrap();
     writeln!(f, "pub const S0: [u8; 256] = {:?};", s0)?;
+    writeln!(f, "pub const S0_INV: [u8; 256] = {:?};", s0_inv)?;
     writeln!(f, "pub const GF28_INV: [u8; 256] = {:?};", gf28_inv)?;
-    writeln!(
-        f,
-        "pub static GF28_TABLE: [[u8; 256]; 256] = {:?};",
-        gf28_table
-    )?;
-    writeln!(f, "pub const RC: [u8; 32] = {:?};", rc_lis)?;
+    if env::var_os("CARGO_FEATURE_COMPACT").is_none() {
+        writeln!(
+            f,
+            "pub static GF28_TABLE: [[u8; 256]; 256] = {:?};",
+            gf28_table
+        )?;
+    }
+    writeln!(f, "pub const GF28_LOG: [u8; 256] = {:?};", gf28_log)?;
+    writeln!(f, "pub const GF28_EXP: [u8; 256] = {:?};", gf28_exp)?;
+    writeln!(f, "pub const RC: [u8; 64] = {:?};", rc_lis)?;
     println!("cargo:return-if-changed=build.rs");
 
     Ok(())
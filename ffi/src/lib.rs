@@ -0,0 +1,9 @@
+/*!
+ * Shared and static library of the C API declared in `include/NarrowWay.h`
+ *
+ * The functions live in `narrowway` itself, this crate only links them into
+ * `cdylib` and `staticlib` artifacts, which need `std` for the panic handler
+ * and the allocator, so `narrowway` stays an rlib that builds without it.
+ */
+
+extern crate cipher;
@@ -21,6 +21,14 @@ void free_cipher_512(uint8_t *);
 void nw_encrypt_512(Cipher512, uint8_t *, uint8_t *);
 void nw_decrypt_512(Cipher256, uint8_t *, uint8_t *);
 
+/* Define NARROWWAY_SELF_TEST when the library is built with the self-test
+ * feature, only then these exist and new_cipher_* returns NULL after a failure */
+#ifdef NARROWWAY_SELF_TEST
+int32_t nw_self_test(void);
+int32_t nw_check_integrity(void);
+int32_t nw_self_test_status(void);
+#endif
+
 #ifdef __cplusplus
 }
 #endif
@@ -0,0 +1,16 @@
+/*!
+ * Tooling which backs the design claims of the specification
+ *
+ * Nothing here is needed to encrypt, it computes properties of the
+ * components so they can be checked and printed with `narrowway analyze`.
+ */
+
+pub mod avalanche;
+pub mod diffusion;
+pub mod equations;
+pub mod keys;
+pub mod randomness;
+pub mod related;
+pub mod sbox;
+pub mod timing;
+pub mod trail;
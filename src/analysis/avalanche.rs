@@ -0,0 +1,283 @@
+use std::fmt;
+
+use crate::rng::SplitMix64;
+use crate::trace::{Observer, Step, Traceable};
+
+/** Input whose bits are flipped */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Plaintext,
+    Key,
+}
+
+impl Input {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Plaintext => "plaintext",
+            Self::Key => "key",
+        }
+    }
+}
+
+/** Flip statistics of the first `rounds` rounds */
+#[derive(Debug, Clone)]
+pub struct Avalanche {
+    pub rounds: usize,
+    pub bits: usize,
+    pub samples: usize,
+    /** `probability[i * bits + j]` that output bit `j` flips when input bit `i` flips */
+    pub probability: Vec<f64>,
+    /** Largest `|correlation|` between the flips of two output bits for one input bit */
+    pub bic: f64,
+}
+
+impl Avalanche {
+    /** Mean fraction of output bits flipped by a single input bit, ideally 0.5 */
+    pub fn avalanche(&self) -> f64 {
+        self.probability.iter().sum::<f64>() / self.probability.len() as f64
+    }
+    /** Largest deviation from 0.5 of one input bit's mean output flip fraction */
+    pub fn avalanche_deviation(&self) -> f64 {
+        self.probability
+            .chunks(self.bits)
+            .map(|row| (row.iter().sum::<f64>() / self.bits as f64 - 0.5).abs())
+            .fold(0.0, f64::max)
+    }
+    /** Largest `|p_ij - 0.5|` of the strict avalanche criterion */
+    pub fn sac_deviation(&self) -> f64 {
+        self.probability
+            .iter()
+            .map(|p| (p - 0.5).abs())
+            .fold(0.0, f64::max)
+    }
+    /** Mean `|p_ij - 0.5|` */
+    pub fn sac_mean_deviation(&self) -> f64 {
+        self.probability
+            .iter()
+            .map(|p| (p - 0.5).abs())
+            .sum::<f64>()
+            / self.probability.len() as f64
+    }
+    /** Every output bit depends on every input bit */
+    pub fn complete(&self) -> bool {
+        self.probability.iter().all(|&p| p > 0.0)
+    }
+    /** The SAC deviation is within sampling noise, see `threshold` */
+    pub fn full_avalanche(&self) -> bool {
+        self.sac_deviation() <= threshold(self.samples)
+    }
+}
+
+/** Six standard deviations of a fair coin estimated from `samples` trials
+ *
+ * A random permutation stays below it over all bit pairs with overwhelming
+ * probability.
+ */
+pub fn threshold(samples: usize) -> f64 {
+    3.0 / (samples as f64).sqrt()
+}
+
+/** Ciphertext after every round */
+struct Rounds(Vec<Vec<u8>>);
+
+impl Observer for Rounds {
+    fn step(&mut self, _round: usize, step: Step, state: &[[u8; 8]]) {
+        if step == Step::ApplyRoundKey {
+            self.0.push(state.concat());
+        }
+    }
+}
+
+fn trace<C: Traceable>(cipher: &C, block: &[u8]) -> Vec<Vec<u8>> {
+    let mut rounds = Rounds(Vec::with_capacity(C::ROUNDS));
+    cipher.encrypt_block_traced(&mut block.to_vec(), &mut rounds);
+    rounds.0
+}
+
+fn flip(bytes: &[u8], bit: usize) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    bytes[bit / 8] ^= 1 << (bit % 8);
+    bytes
+}
+
+/** Largest `|correlation|` between two output bits, `columns[j]` holds the flips of bit `j` per sample */
+fn max_correlation(columns: &[Vec<u64>], samples: usize) -> f64 {
+    let n = samples as f64;
+    let counts: Vec<f64> = columns
+        .iter()
+        .map(|column| column.iter().map(|w| w.count_ones()).sum::<u32>() as f64 / n)
+        .collect();
+    let mut max: f64 = 0.0;
+
+    for j in 0..columns.len() {
+        let pj = counts[j];
+        if pj == 0.0 || pj == 1.0 {
+            continue;
+        }
+        for k in j + 1..columns.len() {
+            let pk = counts[k];
+            if pk == 0.0 || pk == 1.0 {
+                continue;
+            }
+            let joint = columns[j]
+                .iter()
+                .zip(&columns[k])
+                .map(|(a, b)| (a & b).count_ones())
+                .sum::<u32>() as f64
+                / n;
+            let correlation = (joint - pj * pk) / (pj * (1.0 - pj) * pk * (1.0 - pk)).sqrt();
+            max = max.max(correlation.abs());
+        }
+    }
+
+    max
+}
+
+/** Flip every bit of `input` over `samples` random keys and plaintexts
+ *
+ * Returns the statistics after each round count `1..=C::ROUNDS`, the
+ * ciphertext of a reduced-round instance is the state after its last
+ * round. Bit-independence is measured on every `bic_stride`-th input bit,
+ * as it is quadratic in the block size.
+ */
+pub fn measure<C: Traceable>(
+    input: Input,
+    samples: usize,
+    bic_stride: usize,
+    seed: u64,
+) -> Vec<Avalanche> {
+    assert!(samples > 0 && bic_stride > 0);
+    let bits = C::BLOCK_SIZE * 8;
+    let words = samples.div_ceil(64);
+    let mut rng = SplitMix64::new(seed);
+    let mut counts = vec![vec![0u32; bits * bits]; C::ROUNDS];
+    let mut bic = vec![0.0; C::ROUNDS];
+
+    let bases: Vec<(Vec<u8>, Vec<u8>)> = (0..samples)
+        .map(|_| {
+            let mut key = vec![0; C::BLOCK_SIZE];
+            let mut block = vec![0; C::BLOCK_SIZE];
+            rng.fill_bytes(&mut key);
+            rng.fill_bytes(&mut block);
+            (key, block)
+        })
+        .collect();
+    let ciphers: Vec<C> = bases
+        .iter()
+        .map(|(key, _)| C::new_from_slice(key))
+        .collect();
+    let traces: Vec<Vec<Vec<u8>>> = ciphers
+        .iter()
+        .zip(&bases)
+        .map(|(cipher, (_, block))| trace(cipher, block))
+        .collect();
+
+    for i in 0..bits {
+        let track = i % bic_stride == 0;
+        let mut columns = vec![vec![vec![0u64; words]; bits]; if track { C::ROUNDS } else { 0 }];
+
+        for (s, ((key, block), base)) in bases.iter().zip(&traces).enumerate() {
+            let flipped = match input {
+                Input::Plaintext => trace(&ciphers[s], &flip(block, i)),
+                Input::Key => trace(&C::new_from_slice(&flip(key, i)), block),
+            };
+
+            for (round, (a, b)) in base.iter().zip(&flipped).enumerate() {
+                for (byte, (x, y)) in a.iter().zip(b).enumerate() {
+                    let mut diff = x ^ y;
+                    while diff != 0 {
+                        let j = byte * 8 + diff.trailing_zeros() as usize;
+                        counts[round][i * bits + j] += 1;
+                        if track {
+                            columns[round][j][s / 64] |= 1 << (s % 64);
+                        }
+                        diff &= diff - 1;
+                    }
+                }
+            }
+        }
+
+        for (round, columns) in columns.iter().enumerate() {
+            bic[round] = f64::max(bic[round], max_correlation(columns, samples));
+        }
+    }
+
+    counts
+        .into_iter()
+        .zip(bic)
+        .enumerate()
+        .map(|(round, (counts, bic))| Avalanche {
+            rounds: round + 1,
+            bits,
+            samples,
+            probability: counts
+                .into_iter()
+                .map(|n| n as f64 / samples as f64)
+                .collect(),
+            bic,
+        })
+        .collect()
+}
+
+/** Per-round table printed by `narrowway analyze avalanche` */
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub input: Input,
+    pub rounds: Vec<Avalanche>,
+}
+
+impl Report {
+    pub fn new<C: Traceable>(input: Input, samples: usize, seed: u64) -> Self {
+        Self {
+            input,
+            rounds: measure::<C>(input, samples, 8, seed),
+        }
+    }
+    /** First round count reaching full avalanche which every later one keeps */
+    pub fn full_avalanche_round(&self) -> Option<usize> {
+        let last = self
+            .rounds
+            .iter()
+            .rposition(|stats| !stats.full_avalanche());
+        match last {
+            None => Some(1),
+            Some(last) if last + 1 < self.rounds.len() => Some(last + 2),
+            Some(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = &self.rounds[0];
+        writeln!(
+            f,
+            "NarrowWay-{} {} bit flips, {} samples, SAC threshold {:.4}",
+            first.bits,
+            self.input.name(),
+            first.samples,
+            threshold(first.samples)
+        )?;
+        writeln!(
+            f,
+            "rounds  avalanche  aval dev  SAC max  SAC mean  BIC max  complete"
+        )?;
+        for stats in &self.rounds {
+            writeln!(
+                f,
+                "{:>6}  {:>9.4}  {:>8.4}  {:>7.4}  {:>8.4}  {:>7.4}  {}",
+                stats.rounds,
+                stats.avalanche(),
+                stats.avalanche_deviation(),
+                stats.sac_deviation(),
+                stats.sac_mean_deviation(),
+                stats.bic,
+                if stats.complete() { "yes" } else { "no" }
+            )?;
+        }
+        match self.full_avalanche_round() {
+            Some(round) => writeln!(f, "full avalanche after {round} rounds"),
+            None => writeln!(f, "full avalanche not reached"),
+        }
+    }
+}
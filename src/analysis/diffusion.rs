@@ -0,0 +1,325 @@
+use std::fmt;
+
+use crate::{func_f, func_f_inv};
+
+/** 64×64 matrix over GF(2) acting on a row of the state read as a little-endian `u64`
+ *
+ * Column `i` is the image of bit `i`, bit `j` of byte `b` is bit `8 * b + j`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitMatrix {
+    columns: [u64; 64],
+}
+
+impl BitMatrix {
+    /** Matrix of a map, which is assumed to be linear */
+    pub fn from_linear(f: impl Fn(u64) -> u64) -> Self {
+        Self {
+            columns: std::array::from_fn(|i| f(1 << i)),
+        }
+    }
+    pub fn column(&self, i: usize) -> u64 {
+        self.columns[i]
+    }
+    pub fn apply(&self, mut x: u64) -> u64 {
+        let mut y = 0;
+
+        while x != 0 {
+            y ^= self.columns[x.trailing_zeros() as usize];
+            x &= x - 1;
+        }
+
+        y
+    }
+    pub fn transpose(&self) -> Self {
+        Self::from_linear(|x| {
+            let i = x.trailing_zeros();
+            (0..64).fold(0, |y, j| y | (((self.columns[j] >> i) & 1) << j))
+        })
+    }
+    pub fn rank(&self) -> u32 {
+        let mut rows = self.transpose().columns;
+        let mut rank = 0;
+
+        for bit in 0..64 {
+            let Some(pivot) = (rank..64).find(|&r| (rows[r] >> bit) & 1 == 1) else {
+                continue;
+            };
+            rows.swap(rank, pivot);
+            for r in 0..64 {
+                if r != rank && (rows[r] >> bit) & 1 == 1 {
+                    rows[r] ^= rows[rank];
+                }
+            }
+            rank += 1;
+        }
+
+        rank as u32
+    }
+    /** Bitmask of the input bytes which output byte `byte` depends on */
+    pub fn byte_dependencies(&self, byte: usize) -> u8 {
+        (0..8)
+            .filter(|&i| (0..8).any(|j| (self.columns[8 * i + j] >> (8 * byte)) as u8 != 0))
+            .fold(0, |mask, i| mask | (1 << i))
+    }
+}
+
+impl fmt::Display for BitMatrix {
+    /** One line per output bit, input bit 0 first */
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.transpose().columns {
+            for i in 0..64 {
+                if i > 0 && i % 8 == 0 {
+                    f.write_str(" ")?;
+                }
+                f.write_str(if (row >> i) & 1 == 1 { "1" } else { "0" })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn apply_bytes(x: u64, f: fn(&mut [u8; 8], [u8; 8])) -> u64 {
+    let mut bytes = x.to_le_bytes();
+    f(&mut bytes, [0; 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/** Matrix of Function F without the round key */
+pub fn f_matrix() -> BitMatrix {
+    BitMatrix::from_linear(|x| apply_bytes(x, func_f))
+}
+
+/** Matrix of the inverse of Function F without the round key */
+pub fn f_inv_matrix() -> BitMatrix {
+    BitMatrix::from_linear(|x| apply_bytes(x, func_f_inv))
+}
+
+/** Unit in which the weight of a vector is counted */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weight {
+    Bits,
+    Bytes,
+}
+
+impl Weight {
+    fn of(self, x: u64) -> u32 {
+        match self {
+            Self::Bits => x.count_ones(),
+            Self::Bytes => x.to_le_bytes().iter().filter(|&&b| b != 0).count() as u32,
+        }
+    }
+    fn unit(self) -> (usize, u64) {
+        match self {
+            Self::Bits => (1, 1),
+            Self::Bytes => (8, 0xff),
+        }
+    }
+}
+
+/** Call `visit` with every vector of weight `1..=max` */
+fn for_each_of_weight(weight: Weight, max: u32, visit: &mut impl FnMut(u64)) {
+    fn recurse(
+        width: usize,
+        values: u64,
+        first: usize,
+        left: u32,
+        x: u64,
+        visit: &mut impl FnMut(u64),
+    ) {
+        for unit in first..64 / width {
+            for value in 1..=values {
+                let x = x | (value << (unit * width));
+                visit(x);
+                if left > 1 {
+                    recurse(width, values, unit + 1, left - 1, x, visit);
+                }
+            }
+        }
+    }
+
+    let (width, values) = weight.unit();
+    recurse(width, values, 0, max, 0, visit);
+}
+
+/** `min wt(x) + wt(Mx)` over every non-zero `x`, given `M` and its inverse
+ *
+ * Every `x` and every `Mx` of weight at most `depth` is tried. A pair missed
+ * that way weighs at least `2 * depth + 2`, so the result is exact if it is no
+ * larger than that and `None` otherwise.
+ */
+pub fn branch_number(
+    matrix: &BitMatrix,
+    inverse: &BitMatrix,
+    weight: Weight,
+    depth: u32,
+) -> Option<u32> {
+    let mut best = u32::MAX;
+
+    for m in [matrix, inverse] {
+        for_each_of_weight(weight, depth, &mut |x| {
+            best = best.min(weight.of(x) + weight.of(m.apply(x)));
+        });
+    }
+
+    (best <= 2 * depth + 2).then_some(best)
+}
+
+/** Differential branch number of Function F */
+pub fn differential_branch_number(weight: Weight) -> u32 {
+    let depth = if weight == Weight::Bits { 3 } else { 2 };
+    branch_number(&f_matrix(), &f_inv_matrix(), weight, depth)
+        .expect("branch number of F is beyond the search depth")
+}
+
+/** Linear branch number of Function F, masks propagate through the transpose */
+pub fn linear_branch_number(weight: Weight) -> u32 {
+    let depth = if weight == Weight::Bits { 3 } else { 2 };
+    let matrix = f_inv_matrix().transpose();
+    let inverse = f_matrix().transpose();
+    branch_number(&matrix, &inverse, weight, depth)
+        .expect("branch number of F is beyond the search depth")
+}
+
+/** Position of byte `(row, col)` after ShiftColumns, column `col` moves down `col % rows` */
+pub fn shift_columns_position(rows: usize, row: usize, col: usize) -> (usize, usize) {
+    ((row + col % rows) % rows, col)
+}
+
+/** Active bytes of the state after one round of ShiftColumns, SubstituteBytes and F */
+fn round_activity(active: &[u8], rows: usize, f: &BitMatrix) -> Vec<u8> {
+    let mut shifted = vec![0u8; rows];
+    for (row, mask) in active.iter().enumerate() {
+        for col in (0..8).filter(|col| (mask >> col) & 1 == 1) {
+            let (row, col) = shift_columns_position(rows, row, col);
+            shifted[row] |= 1 << col;
+        }
+    }
+
+    /* the S-Boxes keep positions, F mixes within a row */
+    shifted
+        .iter()
+        .map(|&mask| {
+            (0..8)
+                .filter(|&byte| f.byte_dependencies(byte) & mask != 0)
+                .fold(0, |out, byte| out | (1 << byte))
+        })
+        .collect()
+}
+
+/** Rounds after which a single active byte has reached every byte of the state */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diffusion {
+    pub rows: usize,
+    /** Rounds needed from each starting byte, indexed by `row * 8 + col` */
+    pub rounds: Vec<u32>,
+}
+
+impl Diffusion {
+    /** Propagate byte activity for a state of `rows` rows, `None` if it never diffuses fully */
+    pub fn new(rows: usize) -> Option<Self> {
+        let f = f_matrix();
+        let mut rounds = Vec::with_capacity(rows * 8);
+
+        for start in 0..rows * 8 {
+            let mut active = vec![0u8; rows];
+            active[start / 8] = 1 << (start % 8);
+            let mut count = 0;
+
+            while active.iter().any(|&mask| mask != 0xff) {
+                active = round_activity(&active, rows, &f);
+                count += 1;
+                if count > 64 {
+                    return None;
+                }
+            }
+            rounds.push(count);
+        }
+
+        Some(Self { rows, rounds })
+    }
+    /** Rounds until every starting byte has diffused fully */
+    pub fn full(&self) -> u32 {
+        *self.rounds.iter().max().unwrap()
+    }
+    /** Rounds for the fastest starting byte */
+    pub fn fastest(&self) -> u32 {
+        *self.rounds.iter().min().unwrap()
+    }
+}
+
+/** Summary of the linear layer printed by `narrowway analyze diffusion` */
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub rank: u32,
+    pub differential_bits: u32,
+    pub differential_bytes: u32,
+    pub linear_bits: u32,
+    pub linear_bytes: u32,
+    /** Bitmask of input bytes per output byte of F */
+    pub dependencies: [u8; 8],
+    pub diffusion: Vec<Diffusion>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        let f = f_matrix();
+
+        Self {
+            rank: f.rank(),
+            differential_bits: differential_branch_number(Weight::Bits),
+            differential_bytes: differential_branch_number(Weight::Bytes),
+            linear_bits: linear_branch_number(Weight::Bits),
+            linear_bytes: linear_branch_number(Weight::Bytes),
+            dependencies: std::array::from_fn(|byte| f.byte_dependencies(byte)),
+            diffusion: [4, 6, 8]
+                .into_iter()
+                .map(|rows| Diffusion::new(rows).expect("state never diffuses fully"))
+                .collect(),
+        }
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Function F")?;
+        writeln!(f, "rank:                       {}", self.rank)?;
+        writeln!(
+            f,
+            "differential branch number: {} bytes, {} bits",
+            self.differential_bytes, self.differential_bits
+        )?;
+        writeln!(
+            f,
+            "linear branch number:       {} bytes, {} bits",
+            self.linear_bytes, self.linear_bits
+        )?;
+        for (byte, mask) in self.dependencies.iter().enumerate() {
+            let inputs: Vec<String> = (0..8)
+                .filter(|i| (mask >> i) & 1 == 1)
+                .map(|i| i.to_string())
+                .collect();
+            writeln!(f, "output byte {byte} depends on:  {}", inputs.join(" "))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "ShiftColumns + F, one active byte")?;
+        for diffusion in &self.diffusion {
+            writeln!(
+                f,
+                "{}x8 state: full diffusion after {} rounds, fastest byte after {}",
+                diffusion.rows,
+                diffusion.full(),
+                diffusion.fastest()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,687 @@
+/*!
+ * Equation systems of reduced-round NarrowWay for SAT and Gröbner basis solvers
+ *
+ * A `System` is a circuit over GF(2) where every variable is either a free
+ * input, a symbolic key or plaintext bit, or defined by one gate from earlier
+ * variables. It covers the key schedule with the inversions and the S-Box
+ * digests, ShiftColumns, the round S-Boxes `S0 ^ c` and Function F, and is
+ * written as DIMACS CNF or as ANF polynomials. Bits which are not chosen to
+ * be symbolic are folded into constants while the system is built.
+ */
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+use super::diffusion::{BitMatrix, f_matrix, shift_columns_position};
+use super::sbox;
+use crate::{GF28_M, RC, S0, SBox, func_f, gf_mul, gf_mul_inv, rc};
+
+/** A constant or variable `n`, which is `x{n}` in ANF and `n + 1` in DIMACS */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bit {
+    Const(bool),
+    Var(usize),
+}
+
+/** Bits of a byte, least significant first */
+pub type Byte = [Bit; 8];
+
+/** Definition of a variable from earlier variables */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gate {
+    /** Free symbolic bit */
+    Input,
+    /** XOR of the variables and a constant */
+    Xor(Vec<usize>, bool),
+    And(usize, usize),
+    /** Bit `bit` of a lookup in `System::tables()[table]` */
+    Table {
+        inputs: Byte,
+        table: usize,
+        bit: u32,
+    },
+}
+
+/** Largest number of variables XORed by a single gate, which needs `2^n` clauses */
+const MAX_XOR: usize = 4;
+
+/** Lookup tables used by the gates, indexes into `System::tables` */
+const S0_TABLE: usize = 0;
+const INV_TABLE: usize = 1;
+const CLAMP_TABLE: usize = 2;
+
+fn bits_of(byte: u8) -> Byte {
+    std::array::from_fn(|i| Bit::Const((byte >> i) & 1 == 1))
+}
+
+fn rotate_left(byte: Byte, n: usize) -> Byte {
+    std::array::from_fn(|i| byte[(i + 8 - n % 8) % 8])
+}
+
+fn bit_value(bit: Bit, assignment: &[bool]) -> bool {
+    match bit {
+        Bit::Const(c) => c,
+        Bit::Var(v) => assignment[v],
+    }
+}
+
+/** Bytes of `bits` under an assignment from `System::evaluate`, such as the key of a solution */
+pub fn bytes_of(bits: &[Bit], assignment: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|byte| {
+            byte.iter().enumerate().fold(0, |x, (i, &bit)| {
+                x | (bit_value(bit, assignment) as u8) << i
+            })
+        })
+        .collect()
+}
+
+/** Matrix of the round key part of Function F, `F(x, k) == F(x, 0) ^ F(0, k)` */
+fn f_key_matrix() -> BitMatrix {
+    BitMatrix::from_linear(|k| {
+        let mut bytes = [0; 8];
+        func_f(&mut bytes, k.to_le_bytes());
+        u64::from_le_bytes(bytes)
+    })
+}
+
+/** Circuit of a reduced-round encryption, see the module documentation */
+#[derive(Debug, Clone)]
+pub struct System {
+    gates: Vec<Gate>,
+    tables: Vec<SBox>,
+    /** Variables required to take a value, written as unit clauses */
+    fixed: Vec<(usize, bool)>,
+    /** A constant was fixed to the wrong value, no assignment satisfies the system */
+    contradiction: bool,
+    pub rows: usize,
+    pub rounds: usize,
+    /** Bit `8 * i + j` is bit `j` of byte `i` of the master key */
+    pub key: Vec<Bit>,
+    pub plaintext: Vec<Bit>,
+    pub ciphertext: Vec<Bit>,
+}
+
+impl System {
+    /** Encryption of `plaintext` under `key` over whole rounds `0..rounds`
+     *
+     * Set bits of `key_mask` and `plaintext_mask` stay symbolic, the values of
+     * those bits in `key` and `plaintext` are ignored.
+     */
+    pub fn encryption(
+        rounds: usize,
+        key: &[u8],
+        key_mask: &[u8],
+        plaintext: &[u8],
+        plaintext_mask: &[u8],
+    ) -> Self {
+        let rows = key.len() / 8;
+        assert!(
+            matches!(rows, 4 | 6 | 8) && key.len() == rows * 8,
+            "key must be 32, 48 or 64 bytes"
+        );
+        for bytes in [key_mask, plaintext, plaintext_mask] {
+            assert_eq!(
+                bytes.len(),
+                key.len(),
+                "every block must be as long as the key"
+            );
+        }
+        assert!(
+            (1..=RC.len()).contains(&rounds),
+            "between 1 and {} rounds are supported",
+            RC.len()
+        );
+
+        let mut system = Self {
+            gates: Vec::new(),
+            tables: vec![
+                S0,
+                std::array::from_fn(|x| gf_mul_inv(x as u8, GF28_M)),
+                std::array::from_fn(|x| (x as u8).max(1)),
+            ],
+            fixed: Vec::new(),
+            contradiction: false,
+            rows,
+            rounds,
+            key: Vec::new(),
+            plaintext: Vec::new(),
+            ciphertext: Vec::new(),
+        };
+
+        let mut round_key = system.load(key, key_mask);
+        let mut state = system.load(plaintext, plaintext_mask);
+        system.key = round_key.as_flattened().to_vec();
+        system.plaintext = state.as_flattened().to_vec();
+
+        let f = f_matrix();
+        let f_key = f_key_matrix();
+        for round in 0..rounds {
+            round_key = system.round_key(&round_key, round);
+
+            let mut shifted = vec![[Bit::Const(false); 8]; 8 * rows];
+            for (i, byte) in state.iter().enumerate() {
+                let (row, col) = shift_columns_position(rows, i / 8, i % 8);
+                shifted[8 * row + col] = *byte;
+            }
+
+            for row in 0..rows {
+                let key_row = &round_key[8 * row..8 * row + 8];
+                let digest = system.digest(key_row);
+                let substituted: Vec<Bit> = shifted[8 * row..8 * row + 8]
+                    .iter()
+                    .flat_map(|&byte| {
+                        let s = system.table(byte, S0_TABLE);
+                        system.xor_bytes(s, digest)
+                    })
+                    .collect();
+                let key_bits = key_row.as_flattened();
+
+                for bit in 0..64 {
+                    let mut terms: Vec<Bit> = (0..64)
+                        .filter(|&i| (f.column(i) >> bit) & 1 == 1)
+                        .map(|i| substituted[i])
+                        .collect();
+                    terms.extend(
+                        (0..64)
+                            .filter(|&i| (f_key.column(i) >> bit) & 1 == 1)
+                            .map(|i| key_bits[i]),
+                    );
+                    state[8 * row + bit / 8][bit % 8] = system.xor(&terms);
+                }
+            }
+        }
+
+        system.ciphertext = state.as_flattened().to_vec();
+        system
+    }
+    fn load(&mut self, bytes: &[u8], mask: &[u8]) -> Vec<Byte> {
+        bytes
+            .iter()
+            .zip(mask)
+            .map(|(&byte, &mask)| {
+                std::array::from_fn(|i| {
+                    if (mask >> i) & 1 == 1 {
+                        self.push(Gate::Input)
+                    } else {
+                        Bit::Const((byte >> i) & 1 == 1)
+                    }
+                })
+            })
+            .collect()
+    }
+    fn push(&mut self, gate: Gate) -> Bit {
+        self.gates.push(gate);
+        Bit::Var(self.gates.len() - 1)
+    }
+    fn xor(&mut self, bits: &[Bit]) -> Bit {
+        let mut constant = false;
+        let mut vars = BTreeSet::new();
+
+        for &bit in bits {
+            match bit {
+                Bit::Const(c) => constant ^= c,
+                Bit::Var(v) => {
+                    if !vars.remove(&v) {
+                        vars.insert(v);
+                    }
+                }
+            }
+        }
+
+        let mut vars: Vec<usize> = vars.into_iter().collect();
+        while vars.len() > MAX_XOR {
+            let Bit::Var(v) = self.push(Gate::Xor(vars.split_off(vars.len() - MAX_XOR), false))
+            else {
+                unreachable!()
+            };
+            vars.push(v);
+        }
+
+        match vars.as_slice() {
+            [] => Bit::Const(constant),
+            [v] if !constant => Bit::Var(*v),
+            _ => self.push(Gate::Xor(vars, constant)),
+        }
+    }
+    fn and(&mut self, a: Bit, b: Bit) -> Bit {
+        match (a, b) {
+            (Bit::Const(false), _) | (_, Bit::Const(false)) => Bit::Const(false),
+            (Bit::Const(true), bit) | (bit, Bit::Const(true)) => bit,
+            (Bit::Var(a), Bit::Var(b)) if a == b => Bit::Var(a),
+            (Bit::Var(a), Bit::Var(b)) => self.push(Gate::And(a, b)),
+        }
+    }
+    fn xor_bytes(&mut self, a: Byte, b: Byte) -> Byte {
+        std::array::from_fn(|i| self.xor(&[a[i], b[i]]))
+    }
+    fn table(&mut self, inputs: Byte, table: usize) -> Byte {
+        if let Some(x) = inputs.iter().rev().try_fold(0u8, |x, bit| match bit {
+            Bit::Const(c) => Some(x << 1 | *c as u8),
+            Bit::Var(_) => None,
+        }) {
+            return bits_of(self.tables[table][x as usize]);
+        }
+
+        let lookup = self.tables[table];
+        std::array::from_fn(|bit| {
+            let output = |x: usize| (lookup[x] >> bit) & 1 == 1;
+            /* bits which only pass an input through need no gate */
+            if let Some(i) = (0..8).find(|i| (0..256).all(|x| output(x) == ((x >> i) & 1 == 1))) {
+                return inputs[i];
+            }
+            self.push(Gate::Table {
+                inputs,
+                table,
+                bit: bit as u32,
+            })
+        })
+    }
+    fn mul(&mut self, a: Byte, b: Byte) -> Byte {
+        let mut terms: [Vec<Bit>; 8] = Default::default();
+
+        for (i, &a) in a.iter().enumerate() {
+            for (j, &b) in b.iter().enumerate() {
+                let product = self.and(a, b);
+                let reduced = gf_mul(1 << i, 1 << j, GF28_M);
+                for (t, terms) in terms.iter_mut().enumerate() {
+                    if (reduced >> t) & 1 == 1 {
+                        terms.push(product);
+                    }
+                }
+            }
+        }
+
+        terms.map(|terms| self.xor(&terms))
+    }
+    /** Symbolic `round_key_gen` */
+    fn round_key(&mut self, key: &[Byte], round: usize) -> Vec<Byte> {
+        let mut round_key: Vec<Byte> = Vec::with_capacity(key.len());
+
+        for (i, &byte) in key.iter().enumerate() {
+            let inverse = self.table(rotate_left(byte, 4), INV_TABLE);
+            let previous = match i {
+                0 => bits_of(rc(round)),
+                _ => round_key[i - 1],
+            };
+            round_key.push(self.xor_bytes(inverse, previous));
+        }
+
+        round_key
+    }
+    /** Symbolic `digest_key` of one row of a round key */
+    fn digest(&mut self, row: &[Byte]) -> Byte {
+        let mut digest = self.table(row[0], CLAMP_TABLE);
+
+        for &byte in &row[1..] {
+            let clamped = self.table(byte, CLAMP_TABLE);
+            digest = self.mul(digest, clamped);
+        }
+
+        digest
+    }
+    /** Require `bits` to equal the bits of `bytes`, such as a known ciphertext */
+    pub fn fix(&mut self, bits: &[Bit], bytes: &[u8]) {
+        assert_eq!(
+            bits.len(),
+            8 * bytes.len(),
+            "one byte per 8 bits is required"
+        );
+
+        for (i, &bit) in bits.iter().enumerate() {
+            let value = (bytes[i / 8] >> (i % 8)) & 1 == 1;
+            match bit {
+                Bit::Const(c) => self.contradiction |= c != value,
+                Bit::Var(v) => self.fixed.push((v, value)),
+            }
+        }
+    }
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+    pub fn tables(&self) -> &[SBox] {
+        &self.tables
+    }
+    pub fn variables(&self) -> usize {
+        self.gates.len()
+    }
+    /** Variables of the free inputs, in the order `evaluate` takes their values */
+    pub fn inputs(&self) -> Vec<usize> {
+        (0..self.gates.len())
+            .filter(|&v| self.gates[v] == Gate::Input)
+            .collect()
+    }
+    /** Value of every variable for the given values of the inputs */
+    pub fn evaluate(&self, inputs: &[bool]) -> Vec<bool> {
+        let mut inputs = inputs.iter();
+        let mut assignment = Vec::with_capacity(self.gates.len());
+
+        for gate in &self.gates {
+            let value = match gate {
+                Gate::Input => *inputs.next().expect("too few input values"),
+                Gate::Xor(vars, constant) => vars.iter().fold(*constant, |x, &v| x ^ assignment[v]),
+                Gate::And(a, b) => assignment[*a] & assignment[*b],
+                Gate::Table { inputs, table, bit } => {
+                    let x = inputs
+                        .iter()
+                        .rev()
+                        .fold(0, |x, &b| x << 1 | bit_value(b, &assignment) as usize);
+                    (self.tables[*table][x] >> bit) & 1 == 1
+                }
+            };
+            assignment.push(value);
+        }
+
+        assignment
+    }
+    /** Every assignment of the inputs satisfying `formula`, by exhaustive search
+     *
+     * Only the inputs are enumerated, every other variable is taken from
+     * `evaluate`, so this is meant for a handful of symbolic bits.
+     */
+    pub fn solutions(&self, formula: impl Fn(&[bool]) -> bool) -> Vec<Vec<bool>> {
+        let inputs = self.inputs().len();
+        assert!(inputs < 32, "too many symbolic bits to search");
+
+        (0..1u64 << inputs)
+            .map(|n| (0..inputs).map(|i| (n >> i) & 1 == 1).collect::<Vec<_>>())
+            .filter(|inputs| formula(&self.evaluate(inputs)))
+            .collect()
+    }
+    /** Clauses of the system, DIMACS literals of the variables `n + 1` */
+    pub fn clauses(&self) -> Vec<Vec<i64>> {
+        let literal = |v: usize, value: bool| if value { v as i64 + 1 } else { -(v as i64) - 1 };
+        let mut clauses = Vec::new();
+
+        for (out, gate) in self.gates.iter().enumerate() {
+            match gate {
+                Gate::Input => {}
+                Gate::Xor(vars, constant) => {
+                    let mut vars = vars.clone();
+                    vars.push(out);
+                    /* forbid every assignment of the wrong parity */
+                    for n in 0..1u32 << vars.len() {
+                        if (n.count_ones() % 2 == 1) != *constant {
+                            clauses.push(
+                                (0..vars.len())
+                                    .map(|i| literal(vars[i], (n >> i) & 1 == 0))
+                                    .collect(),
+                            );
+                        }
+                    }
+                }
+                Gate::And(a, b) => {
+                    clauses.push(vec![literal(out, false), literal(*a, true)]);
+                    clauses.push(vec![literal(out, false), literal(*b, true)]);
+                    clauses.push(vec![
+                        literal(out, true),
+                        literal(*a, false),
+                        literal(*b, false),
+                    ]);
+                }
+                Gate::Table { inputs, table, bit } => {
+                    /* an input value implies the output bit */
+                    'values: for x in 0..256 {
+                        let mut clause = Vec::with_capacity(9);
+                        for (i, &input) in inputs.iter().enumerate() {
+                            let set = (x >> i) & 1 == 1;
+                            match input {
+                                Bit::Const(c) if c != set => continue 'values,
+                                Bit::Const(_) => {}
+                                Bit::Var(v) => {
+                                    if clause.contains(&literal(v, set)) {
+                                        continue 'values;
+                                    }
+                                    clause.push(literal(v, !set));
+                                }
+                            }
+                        }
+                        clause.sort_unstable();
+                        clause.dedup();
+                        clause.push(literal(out, (self.tables[*table][x] >> bit) & 1 == 1));
+                        clauses.push(clause);
+                    }
+                }
+            }
+        }
+
+        for &(v, value) in &self.fixed {
+            clauses.push(vec![literal(v, value)]);
+        }
+        if self.contradiction {
+            clauses.push(Vec::new());
+        }
+
+        clauses
+    }
+    /** Polynomials of the system which all vanish, as sets of monomials of variables */
+    pub fn polynomials(&self) -> Vec<Polynomial> {
+        let mut polynomials = Vec::new();
+
+        for (out, gate) in self.gates.iter().enumerate() {
+            let mut polynomial = Polynomial::default();
+            polynomial.toggle(vec![out]);
+
+            match gate {
+                Gate::Input => continue,
+                Gate::Xor(vars, constant) => {
+                    for &v in vars {
+                        polynomial.toggle(vec![v]);
+                    }
+                    if *constant {
+                        polynomial.toggle(Vec::new());
+                    }
+                }
+                Gate::And(a, b) => polynomial.toggle(vec![*a, *b]),
+                Gate::Table { inputs, table, bit } => {
+                    let anf = sbox::anf(&self.tables[*table], *bit);
+                    'monomials: for m in (0..256).filter(|&m| anf[m] == 1) {
+                        let mut monomial = Vec::new();
+                        for (i, &input) in inputs.iter().enumerate() {
+                            match input {
+                                _ if (m >> i) & 1 == 0 => {}
+                                Bit::Const(false) => continue 'monomials,
+                                Bit::Const(true) => {}
+                                Bit::Var(v) => monomial.push(v),
+                            }
+                        }
+                        polynomial.toggle(monomial);
+                    }
+                }
+            }
+
+            polynomials.push(polynomial);
+        }
+
+        for &(v, value) in &self.fixed {
+            let mut polynomial = Polynomial::default();
+            polynomial.toggle(vec![v]);
+            if value {
+                polynomial.toggle(Vec::new());
+            }
+            polynomials.push(polynomial);
+        }
+        if self.contradiction {
+            let mut polynomial = Polynomial::default();
+            polynomial.toggle(Vec::new());
+            polynomials.push(polynomial);
+        }
+
+        polynomials
+    }
+    fn describe(&self, f: &mut impl Write, comment: &str, offset: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{comment} NarrowWay-{}, {} rounds",
+            self.rows * 64,
+            self.rounds
+        )?;
+        for (name, bits) in [
+            ("key", &self.key),
+            ("plaintext", &self.plaintext),
+            ("ciphertext", &self.ciphertext),
+        ] {
+            let bits: Vec<String> = bits
+                .iter()
+                .map(|bit| match bit {
+                    Bit::Const(c) => (*c as u8).to_string(),
+                    Bit::Var(v) => format!("x{}", v + offset),
+                })
+                .collect();
+            writeln!(f, "{comment} {name} {}", bits.join(" "))?;
+        }
+        Ok(())
+    }
+    /** DIMACS CNF, the comments list the key, plaintext and ciphertext bits */
+    pub fn to_dimacs(&self) -> String {
+        let clauses = self.clauses();
+        let mut text = String::new();
+
+        self.describe(&mut text, "c", 1).unwrap();
+        writeln!(text, "p cnf {} {}", self.gates.len(), clauses.len()).unwrap();
+        for clause in clauses {
+            for literal in clause {
+                write!(text, "{literal} ").unwrap();
+            }
+            text.push_str("0\n");
+        }
+
+        text
+    }
+    /** One polynomial over GF(2) per line which equals zero, in the syntax of Sage and PolyBoRi */
+    pub fn to_anf(&self) -> String {
+        let mut text = String::new();
+
+        self.describe(&mut text, "#", 0).unwrap();
+        for polynomial in self.polynomials() {
+            writeln!(text, "{polynomial}").unwrap();
+        }
+
+        text
+    }
+}
+
+/** Polynomial over GF(2) in variables `x{n}`, a set of monomials which are sorted sets of variables */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Polynomial {
+    pub monomials: BTreeSet<Vec<usize>>,
+}
+
+impl Polynomial {
+    /** Add a monomial, which cancels an equal one */
+    pub fn toggle(&mut self, mut monomial: Vec<usize>) {
+        monomial.sort_unstable();
+        monomial.dedup();
+        if !self.monomials.remove(&monomial) {
+            self.monomials.insert(monomial);
+        }
+    }
+    pub fn evaluate(&self, assignment: &[bool]) -> bool {
+        self.monomials
+            .iter()
+            .filter(|monomial| monomial.iter().all(|&v| assignment[v]))
+            .count()
+            % 2
+            == 1
+    }
+    /** Parse the output of `Display`, such as `x3*x7 + x2 + 1` */
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut polynomial = Self::default();
+
+        for term in text.split('+').map(str::trim) {
+            match term {
+                "0" => {}
+                "1" => polynomial.toggle(Vec::new()),
+                _ => polynomial.toggle(
+                    term.split('*')
+                        .map(|var| var.trim().strip_prefix('x')?.parse().ok())
+                        .collect::<Option<_>>()?,
+                ),
+            }
+        }
+
+        Some(polynomial)
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.monomials.is_empty() {
+            return f.write_str("0");
+        }
+
+        /* highest degree first, the constant last */
+        let mut monomials: Vec<&Vec<usize>> = self.monomials.iter().collect();
+        monomials.sort_by_key(|monomial| std::cmp::Reverse(monomial.len()));
+        let terms: Vec<String> = monomials
+            .into_iter()
+            .map(|monomial| match monomial.as_slice() {
+                [] => "1".to_string(),
+                vars => vars
+                    .iter()
+                    .map(|v| format!("x{v}"))
+                    .collect::<Vec<_>>()
+                    .join("*"),
+            })
+            .collect();
+        f.write_str(&terms.join(" + "))
+    }
+}
+
+/** Clauses read back from DIMACS CNF */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cnf {
+    pub variables: usize,
+    pub clauses: Vec<Vec<i64>>,
+}
+
+impl Cnf {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('c'));
+        let header: Vec<&str> = lines.next()?.split_whitespace().collect();
+        let ["p", "cnf", variables, count] = header.as_slice() else {
+            return None;
+        };
+        let variables: usize = variables.parse().ok()?;
+
+        let literals: Vec<i64> = lines
+            .flat_map(str::split_whitespace)
+            .map(|literal| literal.parse().ok())
+            .collect::<Option<_>>()?;
+        let clauses: Vec<Vec<i64>> = literals
+            .split(|&literal| literal == 0)
+            .map(<[i64]>::to_vec)
+            .collect();
+        /* the text ends with a 0, which leaves an empty piece after it */
+        let (last, clauses) = clauses.split_last()?;
+        if !last.is_empty()
+            || clauses.len() != count.parse::<usize>().ok()?
+            || clauses
+                .iter()
+                .flatten()
+                .any(|literal| literal.unsigned_abs() as usize > variables)
+        {
+            return None;
+        }
+
+        Some(Self {
+            variables,
+            clauses: clauses.to_vec(),
+        })
+    }
+    pub fn satisfied(&self, assignment: &[bool]) -> bool {
+        self.clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|&literal| assignment[literal.unsigned_abs() as usize - 1] == (literal > 0))
+        })
+    }
+}
+
+/** Polynomials read back from `System::to_anf`, skipping the `#` comments */
+pub fn parse_anf(text: &str) -> Option<Vec<Polynomial>> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(Polynomial::parse)
+        .collect()
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+pub use crate::keys::{Weakness, check, weaknesses};
+use crate::{GF28_M, gf_mul};
+
+/** Number of `len`-byte round-key rows per `digest_key` value, `counts[0]` is always 0
+ *
+ * `digest_key` multiplies `max(byte, 1)` in GF(2^8), so the distribution is
+ * the `len`-fold convolution of the byte distribution over the 255 non-zero
+ * elements, where `1` has twice the weight of the others.
+ */
+pub fn digest_counts(len: usize) -> [u128; 256] {
+    let mut counts = [0u128; 256];
+    counts[1] = 1;
+
+    for _ in 0..len {
+        let mut next = [0u128; 256];
+        for (digest, &count) in counts.iter().enumerate().filter(|(_, count)| **count != 0) {
+            for byte in 0..=255u8 {
+                next[gf_mul(digest as u8, byte.max(1), GF28_M) as usize] += count;
+            }
+        }
+        counts = next;
+    }
+
+    counts
+}
+
+/** Collision classes of `digest_key` over rows of `len` bytes
+ *
+ * Rows in one class generate the same round S-Box. Besides products that
+ * collide in the field, permuting the bytes of a row or replacing a zero
+ * byte with a one never changes the class.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classes {
+    pub len: usize,
+    /** Rows per digest, indexed by digest */
+    pub counts: [u128; 256],
+}
+
+impl Classes {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            counts: digest_counts(len),
+        }
+    }
+    /** Number of rows, `256^len` */
+    pub fn rows(&self) -> u128 {
+        self.counts.iter().sum()
+    }
+    pub fn smallest(&self) -> u128 {
+        *self.counts[1..].iter().min().unwrap()
+    }
+    pub fn largest(&self) -> u128 {
+        *self.counts[1..].iter().max().unwrap()
+    }
+    /** Probability that two uniform rows give the same S-Box */
+    pub fn collision_probability(&self) -> f64 {
+        let rows = self.rows() as f64;
+        self.counts
+            .iter()
+            .map(|&count| (count as f64 / rows).powi(2))
+            .sum()
+    }
+    /** Rényi entropy of order 2 of the digest in bits, at most `log2(255)` */
+    pub fn collision_entropy(&self) -> f64 {
+        -self.collision_probability().log2()
+    }
+    /** Probability that a uniform row holds a zero byte, which the digest reads as a one */
+    pub fn clamped_probability(&self) -> f64 {
+        1.0 - (255.0f64 / 256.0).powi(self.len as i32)
+    }
+}
+
+impl fmt::Display for Classes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.rows() as f64;
+
+        writeln!(f, "digest_key over {}-byte rows", self.len)?;
+        writeln!(f, "classes:               255")?;
+        writeln!(
+            f,
+            "class size:            {} to {} rows, spread {:.3e}",
+            self.smallest(),
+            self.largest(),
+            (self.largest() - self.smallest()) as f64 / self.smallest() as f64
+        )?;
+        writeln!(
+            f,
+            "most likely digest:    {:02x} (p = 2^{:.6})",
+            self.counts
+                .iter()
+                .position(|&n| n == self.largest())
+                .unwrap(),
+            (self.largest() as f64 / rows).log2()
+        )?;
+        writeln!(
+            f,
+            "collision probability: {:.6e}, uniform {:.6e}",
+            self.collision_probability(),
+            1.0 / 255.0
+        )?;
+        writeln!(
+            f,
+            "rows with a zero byte: {:.2}%",
+            100.0 * self.clamped_probability()
+        )
+    }
+}
@@ -0,0 +1,485 @@
+/*!
+ * A subset of the NIST SP 800-22 statistical tests
+ *
+ * Every test returns p-values, a sequence fails a test at the usual
+ * significance level when one of them is below `ALPHA`. Bits are passed as
+ * `bool`s, `bits_of` reads bytes most significant bit first like the NIST
+ * reference implementation.
+ */
+
+use std::f64::consts::{LN_2, SQRT_2};
+use std::fmt;
+
+use crate::modes::ctr_block;
+use crate::rng::SplitMix64;
+use crate::trace::{Observer, Step, Traceable};
+
+/** Significance level of the battery */
+pub const ALPHA: f64 = 0.01;
+
+/** Complementary error function, with a fractional error below 1.2e-7 */
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, c| c + t * acc);
+    let erfc = t * (-z * z + poly).exp();
+
+    if x >= 0.0 { erfc } else { 2.0 - erfc }
+}
+
+/** Cumulative distribution function of the standard normal distribution */
+fn normal(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+/** `ln Γ(x)` for `x > 0`, Lanczos approximation */
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/** Upper regularized incomplete gamma function `Q(a, x)` */
+pub fn igamc(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    const ITERATIONS: usize = 100_000;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        /* series of the lower function */
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        1.0 - sum * prefix
+    } else {
+        /* continued fraction, modified Lentz's method */
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        prefix * h
+    }
+}
+
+/** Bits of `bytes`, most significant bit of every byte first */
+pub fn bits_of(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+        .collect()
+}
+
+fn ones(bits: &[bool]) -> usize {
+    bits.iter().filter(|&&bit| bit).count()
+}
+
+/** Frequency (monobit) test */
+pub fn frequency(bits: &[bool]) -> f64 {
+    let n = bits.len() as f64;
+    let sum = 2.0 * ones(bits) as f64 - n;
+
+    erfc(sum.abs() / (2.0 * n).sqrt())
+}
+
+/** Frequency test within blocks of `m` bits, a trailing partial block is ignored */
+pub fn block_frequency(bits: &[bool], m: usize) -> f64 {
+    let blocks = bits.len() / m;
+    let chi_squared: f64 = bits
+        .chunks_exact(m)
+        .map(|block| {
+            let pi = ones(block) as f64 / m as f64;
+            (pi - 0.5).powi(2)
+        })
+        .sum::<f64>()
+        * 4.0
+        * m as f64;
+
+    igamc(blocks as f64 / 2.0, chi_squared / 2.0)
+}
+
+/** Runs test, 0 when the sequence already fails the frequency prerequisite */
+pub fn runs(bits: &[bool]) -> f64 {
+    let n = bits.len() as f64;
+    let pi = ones(bits) as f64 / n;
+    if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+        return 0.0;
+    }
+
+    let runs = 1 + bits.windows(2).filter(|pair| pair[0] != pair[1]).count();
+    let expected = 2.0 * n * pi * (1.0 - pi);
+
+    erfc((runs as f64 - expected).abs() / (2.0 * (2.0 * n).sqrt() * pi * (1.0 - pi)))
+}
+
+/** Test for the longest run of ones in a block, which needs at least 128 bits */
+pub fn longest_run(bits: &[bool]) -> f64 {
+    let n = bits.len();
+    assert!(n >= 128, "the longest run test needs at least 128 bits");
+
+    let (m, shortest, probabilities): (usize, usize, &[f64]) = if n < 6272 {
+        (8, 1, &[0.2148, 0.3672, 0.2305, 0.1875])
+    } else if n < 750_000 {
+        (128, 4, &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124])
+    } else {
+        (
+            10_000,
+            10,
+            &[0.0882, 0.2092, 0.2483, 0.1933, 0.1208, 0.0675, 0.0727],
+        )
+    };
+
+    let mut counts = vec![0usize; probabilities.len()];
+    for block in bits.chunks_exact(m) {
+        let longest = block
+            .split(|&bit| !bit)
+            .map(<[bool]>::len)
+            .max()
+            .unwrap_or(0);
+        let class = longest.clamp(shortest, shortest + counts.len() - 1) - shortest;
+        counts[class] += 1;
+    }
+
+    let blocks = (n / m) as f64;
+    let chi_squared: f64 = counts
+        .iter()
+        .zip(probabilities)
+        .map(|(&count, p)| (count as f64 - blocks * p).powi(2) / (blocks * p))
+        .sum();
+
+    igamc((probabilities.len() - 1) as f64 / 2.0, chi_squared / 2.0)
+}
+
+/** Occurrences of every overlapping `m`-bit pattern, the sequence wraps around */
+fn pattern_counts(bits: &[bool], m: usize) -> Vec<usize> {
+    let mut counts = vec![0; 1 << m];
+    if m == 0 {
+        return counts;
+    }
+
+    let n = bits.len();
+    let mask = (1 << m) - 1;
+    let mut pattern = (0..m - 1).fold(0, |p, i| p << 1 | bits[i] as usize);
+    for i in 0..n {
+        pattern = (pattern << 1 | bits[(i + m - 1) % n] as usize) & mask;
+        counts[pattern] += 1;
+    }
+
+    counts
+}
+
+fn psi_squared(bits: &[bool], m: usize) -> f64 {
+    if m == 0 {
+        return 0.0;
+    }
+    let n = bits.len() as f64;
+    let sum: f64 = pattern_counts(bits, m)
+        .iter()
+        .map(|&count| (count as f64).powi(2))
+        .sum();
+
+    sum * (1 << m) as f64 / n - n
+}
+
+/** Serial test of the overlapping `m`-bit patterns, both p-values */
+pub fn serial(bits: &[bool], m: usize) -> [f64; 2] {
+    assert!(m >= 3, "the serial test needs patterns of at least 3 bits");
+    let psi = [m, m - 1, m - 2].map(|m| psi_squared(bits, m));
+    let delta = psi[0] - psi[1];
+    let delta_squared = psi[0] - 2.0 * psi[1] + psi[2];
+
+    [
+        igamc((1 << (m - 2)) as f64, delta / 2.0),
+        igamc((1 << (m - 3)) as f64, delta_squared / 2.0),
+    ]
+}
+
+/** Approximate entropy test of the overlapping `m`- and `m + 1`-bit patterns */
+pub fn approximate_entropy(bits: &[bool], m: usize) -> f64 {
+    assert!(
+        m >= 1,
+        "the approximate entropy test needs patterns of at least 1 bit"
+    );
+    let n = bits.len() as f64;
+    let phi = |m: usize| -> f64 {
+        pattern_counts(bits, m)
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / n;
+                p * p.ln()
+            })
+            .sum()
+    };
+    let entropy = phi(m) - phi(m + 1);
+    let chi_squared = 2.0 * n * (LN_2 - entropy);
+
+    igamc((1 << (m - 1)) as f64, chi_squared / 2.0)
+}
+
+fn cumulative_sums_one_way(bits: impl Iterator<Item = bool>, n: usize) -> f64 {
+    let mut sum = 0i64;
+    let mut z = 0;
+    for bit in bits {
+        sum += if bit { 1 } else { -1 };
+        z = z.max(sum.unsigned_abs());
+    }
+
+    let n = n as f64;
+    let z = z as f64;
+    let root = n.sqrt();
+    let terms = |first: f64, a: f64, b: f64| -> f64 {
+        let mut sum = 0.0;
+        let mut k = first.trunc();
+        while k <= (n / z - 1.0) / 4.0 {
+            sum += normal((4.0 * k + a) * z / root) - normal((4.0 * k + b) * z / root);
+            k += 1.0;
+        }
+        sum
+    };
+
+    1.0 - terms((-n / z + 1.0) / 4.0, 1.0, -1.0) + terms((-n / z - 3.0) / 4.0, 3.0, 1.0)
+}
+
+/** Cumulative sums test, forward and backward */
+pub fn cumulative_sums(bits: &[bool]) -> [f64; 2] {
+    [
+        cumulative_sums_one_way(bits.iter().copied(), bits.len()),
+        cumulative_sums_one_way(bits.iter().rev().copied(), bits.len()),
+    ]
+}
+
+/** Name and p-values of one test of the battery */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: &'static str,
+    pub p_values: Vec<f64>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.p_values.iter().all(|&p| p >= ALPHA)
+    }
+    pub fn min(&self) -> f64 {
+        self.p_values.iter().copied().fold(1.0, f64::min)
+    }
+}
+
+/** Every test with the parameters NIST suggests for the length of `bits`
+ *
+ * Needs at least 2^12 bits so that every pattern length is meaningful.
+ */
+pub fn battery(bits: &[bool]) -> Vec<Outcome> {
+    let log = bits.len().ilog2() as usize;
+    assert!(log >= 12, "the battery needs at least 4096 bits");
+    let outcome = |name, p_values| Outcome { name, p_values };
+    /* M >= 20, M > n / 100 and fewer than 100 blocks */
+    let block = (bits.len() / 99 + 1).max(20);
+
+    vec![
+        outcome("frequency", vec![frequency(bits)]),
+        outcome("block frequency", vec![block_frequency(bits, block)]),
+        outcome("runs", vec![runs(bits)]),
+        outcome("longest run", vec![longest_run(bits)]),
+        outcome("serial", serial(bits, (log - 3).min(16)).to_vec()),
+        outcome(
+            "approximate entropy",
+            vec![approximate_entropy(bits, (log - 6).min(10))],
+        ),
+        outcome("cumulative sums", cumulative_sums(bits).to_vec()),
+    ]
+}
+
+/** State after the first `rounds` rounds */
+struct After {
+    rounds: usize,
+    state: Vec<u8>,
+}
+
+impl Observer for After {
+    fn step(&mut self, round: usize, step: Step, state: &[[u8; 8]]) {
+        if round + 1 == self.rounds && step == Step::ApplyRoundKey {
+            self.state = state.concat();
+        }
+    }
+}
+
+fn encrypt_rounds<C: Traceable>(cipher: &C, rounds: usize, block: &[u8]) -> Vec<u8> {
+    let mut after = After {
+        rounds,
+        state: Vec::new(),
+    };
+    cipher.encrypt_block_traced(&mut block.to_vec(), &mut after);
+    after.state
+}
+
+/** Data fed to the battery */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /** CTR keystream of a random key and IV */
+    Keystream,
+    /** `P ^ C` of random plaintexts under one random key */
+    Correlation,
+}
+
+impl Stream {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keystream => "keystream",
+            Self::Correlation => "P ^ C",
+        }
+    }
+    /** At least `bits` bits of the stream through the first `rounds` rounds of `C` */
+    pub fn generate<C: Traceable>(self, rounds: usize, bits: usize, seed: u64) -> Vec<bool> {
+        assert!(
+            (1..=C::ROUNDS).contains(&rounds),
+            "between 1 and {} rounds are supported",
+            C::ROUNDS
+        );
+        let mut rng = SplitMix64::new(seed);
+        let mut key = vec![0; C::BLOCK_SIZE];
+        let mut iv = vec![0; C::BLOCK_SIZE];
+        rng.fill_bytes(&mut key);
+        rng.fill_bytes(&mut iv);
+        let cipher = C::new_from_slice(&key);
+        let mut block = vec![0; C::BLOCK_SIZE];
+        let mut bytes = Vec::with_capacity(bits / 8 + C::BLOCK_SIZE);
+
+        for counter in 0..bits.div_ceil(8 * C::BLOCK_SIZE) as u64 {
+            match self {
+                Self::Keystream => {
+                    ctr_block(&iv, counter, &mut block);
+                    bytes.extend(encrypt_rounds(&cipher, rounds, &block));
+                }
+                Self::Correlation => {
+                    rng.fill_bytes(&mut block);
+                    let ciphertext = encrypt_rounds(&cipher, rounds, &block);
+                    bytes.extend(block.iter().zip(ciphertext).map(|(p, c)| p ^ c));
+                }
+            }
+        }
+
+        bits_of(&bytes)
+    }
+}
+
+/** Battery over both streams per round count, printed by `narrowway analyze randomness` */
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub bits: usize,
+    pub length: usize,
+    /** Stream, rounds and the outcome of every test */
+    pub streams: Vec<(Stream, usize, Vec<Outcome>)>,
+}
+
+impl Report {
+    /** Full rounds and the first `reduced` rounds, `length` bits per stream */
+    pub fn new<C: Traceable>(length: usize, reduced: usize, seed: u64) -> Self {
+        let mut rounds: Vec<usize> = (1..=reduced.min(C::ROUNDS)).collect();
+        if !rounds.contains(&C::ROUNDS) {
+            rounds.push(C::ROUNDS);
+        }
+
+        let streams = rounds
+            .into_iter()
+            .rev()
+            .flat_map(|r| [(Stream::Keystream, r), (Stream::Correlation, r)])
+            .map(|(stream, r)| {
+                let bits = stream.generate::<C>(r, length, seed);
+                (stream, r, battery(&bits))
+            })
+            .collect();
+
+        Self {
+            bits: C::BLOCK_SIZE * 8,
+            length,
+            streams,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "NarrowWay-{}, {} bits per stream, smallest p-value per test, * below {ALPHA}",
+            self.bits, self.length
+        )?;
+        write!(f, "{:<20}", "")?;
+        for (stream, rounds, _) in &self.streams {
+            write!(f, "  {:>15}", format!("{} r{rounds}", stream.name()))?;
+        }
+        writeln!(f)?;
+
+        let Some((_, _, first)) = self.streams.first() else {
+            return Ok(());
+        };
+        for (i, test) in first.iter().enumerate() {
+            write!(f, "{:<20}", test.name)?;
+            for (_, _, outcomes) in &self.streams {
+                let outcome = &outcomes[i];
+                let mark = if outcome.passed() { " " } else { "*" };
+                write!(f, "  {:>14.6}{mark}", outcome.min())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
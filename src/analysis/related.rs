@@ -0,0 +1,261 @@
+/*!
+ * Related-key and slid-key behaviour of the key schedule
+ *
+ * `key_difference` follows a master key difference through the round keys
+ * and S-Box digests. `slid_keys` builds a second key whose first round key is
+ * the second round key of the first, which is what a slide attack needs in
+ * every round, and measures how far the schedules stay slid. It is a
+ * consistency check of that construction, not a search for slid plaintext
+ * pairs: the pair `P' = R_0(P)` is built from the first round, so round 0
+ * always agrees and the later rounds show where the schedules part.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::rng::SplitMix64;
+use crate::trace::{Observer, Step, Traceable};
+use crate::{GF28_M, gf_mul_inv, rc};
+
+/** Round keys, digests and the state after every round of one encryption */
+#[derive(Default)]
+struct Schedule {
+    round_keys: Vec<Vec<u8>>,
+    digests: Vec<Vec<u8>>,
+    states: Vec<Vec<u8>>,
+}
+
+impl Observer for Schedule {
+    fn round_key(&mut self, _round: usize, key: &[u8], digests: &[u8]) {
+        self.round_keys.push(key.to_vec());
+        self.digests.push(digests.to_vec());
+    }
+    fn step(&mut self, _round: usize, step: Step, state: &[[u8; 8]]) {
+        if step == Step::ApplyRoundKey {
+            self.states.push(state.concat());
+        }
+    }
+}
+
+fn schedule<C: Traceable>(key: &[u8], block: &[u8]) -> Schedule {
+    let mut schedule = Schedule::default();
+    C::new_from_slice(key).encrypt_block_traced(&mut block.to_vec(), &mut schedule);
+    schedule
+}
+
+/** Master key whose first round key is `round_key`, the inverse of the schedule of round 0 */
+fn key_for_first_round_key(round_key: &[u8]) -> Vec<u8> {
+    let mut previous = rc(0);
+
+    round_key
+        .iter()
+        .map(|&byte| {
+            let key = gf_mul_inv(byte ^ previous, GF28_M).rotate_right(4);
+            previous = byte;
+            key
+        })
+        .collect()
+}
+
+fn differing(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).filter(|(x, y)| x != y).count()
+}
+
+fn equal_fraction(a: &[u8], b: &[u8]) -> f64 {
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+/** How a master key difference shows up in the keys of one round, averaged over the samples */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundDifference {
+    /** Mean number of round key bytes which differ */
+    pub key_bytes: f64,
+    /** Fraction of rows which keep their S-Box */
+    pub equal_digests: f64,
+    /** Fraction of samples showing the most frequent round key difference */
+    pub fixed: f64,
+}
+
+/** Encrypt under pairs of random keys `k` and `k ^ delta` and compare their schedules per round */
+pub fn key_difference<C: Traceable>(
+    delta: &[u8],
+    samples: usize,
+    seed: u64,
+) -> Vec<RoundDifference> {
+    assert_eq!(
+        delta.len(),
+        C::BLOCK_SIZE,
+        "difference must be as long as the key"
+    );
+    assert!(samples > 0);
+    let mut rng = SplitMix64::new(seed);
+    let mut key_bytes = vec![0; C::ROUNDS];
+    let mut equal_digests = vec![0.0; C::ROUNDS];
+    let mut differences = vec![HashMap::new(); C::ROUNDS];
+
+    for _ in 0..samples {
+        let mut key = vec![0; C::BLOCK_SIZE];
+        rng.fill_bytes(&mut key);
+        let related: Vec<u8> = key.iter().zip(delta).map(|(k, d)| k ^ d).collect();
+        let block = vec![0; C::BLOCK_SIZE];
+        let a = schedule::<C>(&key, &block);
+        let b = schedule::<C>(&related, &block);
+
+        for round in 0..C::ROUNDS {
+            let difference: Vec<u8> = a.round_keys[round]
+                .iter()
+                .zip(&b.round_keys[round])
+                .map(|(x, y)| x ^ y)
+                .collect();
+            key_bytes[round] += differing(&a.round_keys[round], &b.round_keys[round]);
+            equal_digests[round] += equal_fraction(&a.digests[round], &b.digests[round]);
+            *differences[round].entry(difference).or_insert(0usize) += 1;
+        }
+    }
+
+    let n = samples as f64;
+    (0..C::ROUNDS)
+        .map(|round| RoundDifference {
+            key_bytes: key_bytes[round] as f64 / n,
+            equal_digests: equal_digests[round] / n,
+            fixed: *differences[round].values().max().unwrap() as f64 / n,
+        })
+        .collect()
+}
+
+/** Agreement of a slid key pair over one round, averaged over the samples
+ *
+ * The second key is chosen so that its first round key is the second round
+ * key of the first, round `i` of it is compared with round `i + 1` of the
+ * first key.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlidRound {
+    /** Mean number of round key bytes which differ */
+    pub key_bytes: f64,
+    /** Fraction of rows which use the same S-Box */
+    pub equal_digests: f64,
+    /** Fraction of samples whose states still agree after rounds `0..=i`, starting from `P' = R_0(P)` */
+    pub agreeing_states: f64,
+}
+
+/** Build slid key pairs from random keys and check how many rounds they stay slid
+ *
+ * Encrypts a random `P` under the first key and `P' = R_0(P)` under the
+ * second, and compares the state after round `i` of the second key with the
+ * state after round `i + 1` of the first. Round 0 agrees by construction.
+ * Returns `C::ROUNDS - 1` rounds.
+ */
+pub fn slid_keys<C: Traceable>(samples: usize, seed: u64) -> Vec<SlidRound> {
+    assert!(samples > 0);
+    let mut rng = SplitMix64::new(seed);
+    let rounds = C::ROUNDS - 1;
+    let mut key_bytes = vec![0; rounds];
+    let mut equal_digests = vec![0.0; rounds];
+    let mut agreeing_states = vec![0; rounds];
+
+    for _ in 0..samples {
+        let mut key = vec![0; C::BLOCK_SIZE];
+        let mut block = vec![0; C::BLOCK_SIZE];
+        rng.fill_bytes(&mut key);
+        rng.fill_bytes(&mut block);
+
+        let a = schedule::<C>(&key, &block);
+        let slid_key = key_for_first_round_key(&a.round_keys[1]);
+        let b = schedule::<C>(&slid_key, &a.states[0]);
+        debug_assert_eq!(b.round_keys[0], a.round_keys[1]);
+
+        /* the relation holds up to the first round count where the states part */
+        let held = (0..rounds)
+            .take_while(|&i| b.states[i] == a.states[i + 1])
+            .count();
+        for agreeing in &mut agreeing_states[..held] {
+            *agreeing += 1;
+        }
+        for i in 0..rounds {
+            key_bytes[i] += differing(&b.round_keys[i], &a.round_keys[i + 1]);
+            equal_digests[i] += equal_fraction(&b.digests[i], &a.digests[i + 1]);
+        }
+    }
+
+    let n = samples as f64;
+    (0..rounds)
+        .map(|i| SlidRound {
+            key_bytes: key_bytes[i] as f64 / n,
+            equal_digests: equal_digests[i] / n,
+            agreeing_states: agreeing_states[i] as f64 / n,
+        })
+        .collect()
+}
+
+/** Single-bit key differences printed by `narrowway analyze related` */
+pub fn differences(len: usize) -> Vec<(String, Vec<u8>)> {
+    [0, len / 2, len - 1]
+        .into_iter()
+        .map(|byte| {
+            let mut delta = vec![0; len];
+            delta[byte] = 1;
+            (format!("bit 0 of key byte {byte}"), delta)
+        })
+        .collect()
+}
+
+/** Related-key and slide statistics printed by `narrowway analyze related` */
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub bits: usize,
+    pub samples: usize,
+    pub differences: Vec<(String, Vec<RoundDifference>)>,
+    pub slid_keys: Vec<SlidRound>,
+}
+
+impl Report {
+    pub fn new<C: Traceable>(samples: usize, seed: u64) -> Self {
+        Self {
+            bits: C::BLOCK_SIZE * 8,
+            samples,
+            differences: differences(C::BLOCK_SIZE)
+                .into_iter()
+                .map(|(name, delta)| (name, key_difference::<C>(&delta, samples, seed)))
+                .collect(),
+            slid_keys: slid_keys::<C>(samples, seed),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "NarrowWay-{} related keys, {} samples",
+            self.bits, self.samples
+        )?;
+        for (name, rounds) in &self.differences {
+            writeln!(f)?;
+            writeln!(f, "key difference: {name}")?;
+            writeln!(f, "round  key bytes  same S-Box  fixed difference")?;
+            for (round, stats) in rounds.iter().enumerate() {
+                writeln!(
+                    f,
+                    "{:>5}  {:>9.2}  {:>10.4}  {:>16.4}",
+                    round, stats.key_bytes, stats.equal_digests, stats.fixed
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "slid keys, round i of the second key against round i + 1, from P' = R_0(P)"
+        )?;
+        writeln!(f, "round  key bytes  same S-Box  same state")?;
+        for (round, stats) in self.slid_keys.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>5}  {:>9.2}  {:>10.4}  {:>10.4}",
+                round, stats.key_bytes, stats.equal_digests, stats.agreeing_states
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+
+use crate::SBox;
+
+/** Difference distribution table, `ddt[a][b]` counts `x` with `S(x) ^ S(x ^ a) == b` */
+pub fn ddt(s_box: &SBox) -> Box<[[u16; 256]; 256]> {
+    let mut table = Box::new([[0; 256]; 256]);
+
+    for (a, row) in table.iter_mut().enumerate() {
+        for x in 0..256 {
+            row[(s_box[x] ^ s_box[x ^ a]) as usize] += 1;
+        }
+    }
+
+    table
+}
+
+/** Linear approximation table, `lat[a][b]` is `#{x : a·x == b·S(x)} - 128` */
+pub fn lat(s_box: &SBox) -> Box<[[i16; 256]; 256]> {
+    let mut table = Box::new([[0; 256]; 256]);
+
+    /* Walsh-Hadamard transform of every component function b·S */
+    for b in 0..256 {
+        let mut walsh = [0i16; 256];
+        for (x, w) in walsh.iter_mut().enumerate() {
+            *w = if (s_box[x] as usize & b).count_ones().is_multiple_of(2) {
+                1
+            } else {
+                -1
+            };
+        }
+
+        let mut len = 1;
+        while len < 256 {
+            for i in (0..256).step_by(2 * len) {
+                for j in i..i + len {
+                    (walsh[j], walsh[j + len]) =
+                        (walsh[j] + walsh[j + len], walsh[j] - walsh[j + len]);
+                }
+            }
+            len *= 2;
+        }
+
+        for (a, row) in table.iter_mut().enumerate() {
+            row[b] = walsh[a] / 2;
+        }
+    }
+
+    table
+}
+
+/** Largest entry of the DDT outside of the trivial difference 0 */
+pub fn differential_uniformity(ddt: &[[u16; 256]; 256]) -> u16 {
+    ddt[1..].iter().flatten().copied().max().unwrap()
+}
+
+/** `128 - max |lat[a][b]|` over the non-trivial output masks */
+pub fn nonlinearity(lat: &[[i16; 256]; 256]) -> u16 {
+    let max = lat
+        .iter()
+        .flat_map(|row| &row[1..])
+        .map(|entry| entry.unsigned_abs())
+        .max()
+        .unwrap();
+
+    128 - max
+}
+
+/** Algebraic normal form of output bit `bit`, the coefficient of monomial `m` is `anf[m]` */
+pub fn anf(s_box: &SBox, bit: u32) -> [u8; 256] {
+    let mut anf = [0; 256];
+    for (x, coefficient) in anf.iter_mut().enumerate() {
+        *coefficient = (s_box[x] >> bit) & 1;
+    }
+
+    /* Möbius transform */
+    for i in 0..8 {
+        for m in 0..256 {
+            if m & (1 << i) != 0 {
+                anf[m] ^= anf[m ^ (1 << i)];
+            }
+        }
+    }
+
+    anf
+}
+
+/** Largest degree of the ANF over all output bits */
+pub fn algebraic_degree(s_box: &SBox) -> u32 {
+    (0..8)
+        .flat_map(|bit| {
+            let anf = anf(s_box, bit);
+            (0..256usize).filter(move |&m| anf[m] == 1)
+        })
+        .map(|m| m.count_ones())
+        .max()
+        .unwrap_or(0)
+}
+
+/** Inputs with `S(x) == x` */
+pub fn fixed_points(s_box: &SBox) -> Vec<u8> {
+    (0..=255).filter(|&x| s_box[x as usize] == x).collect()
+}
+
+/** Inputs with `S(x) == !x` */
+pub fn opposite_fixed_points(s_box: &SBox) -> Vec<u8> {
+    (0..=255).filter(|&x| s_box[x as usize] == !x).collect()
+}
+
+/** The round S-Box `S0 ^ c` */
+pub fn keyed(s_box: &SBox, c: u8) -> SBox {
+    s_box.map(|byte| byte ^ c)
+}
+
+/** Properties of a single S-Box */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub differential_uniformity: u16,
+    /** Largest probability of a differential is `differential_uniformity / 256` */
+    pub max_differential_probability: f64,
+    pub nonlinearity: u16,
+    /** Largest `|lat| / 256`, the bias of the best linear approximation */
+    pub max_linear_bias: f64,
+    pub algebraic_degree: u32,
+    pub fixed_points: Vec<u8>,
+    pub opposite_fixed_points: Vec<u8>,
+}
+
+impl Summary {
+    pub fn new(s_box: &SBox) -> Self {
+        let differential_uniformity = differential_uniformity(&ddt(s_box));
+        let nonlinearity = nonlinearity(&lat(s_box));
+
+        Self {
+            differential_uniformity,
+            max_differential_probability: differential_uniformity as f64 / 256.0,
+            nonlinearity,
+            max_linear_bias: (128 - nonlinearity) as f64 / 256.0,
+            algebraic_degree: algebraic_degree(s_box),
+            fixed_points: fixed_points(s_box),
+            opposite_fixed_points: opposite_fixed_points(s_box),
+        }
+    }
+}
+
+fn hex_list(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "none".to_string();
+    }
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "differential uniformity: {} (p = 2^{:.2})",
+            self.differential_uniformity,
+            self.max_differential_probability.log2()
+        )?;
+        writeln!(
+            f,
+            "nonlinearity:            {} (bias = 2^{:.2})",
+            self.nonlinearity,
+            self.max_linear_bias.log2()
+        )?;
+        writeln!(f, "algebraic degree:        {}", self.algebraic_degree)?;
+        writeln!(
+            f,
+            "fixed points:            {}",
+            hex_list(&self.fixed_points)
+        )?;
+        writeln!(
+            f,
+            "opposite fixed points:   {}",
+            hex_list(&self.opposite_fixed_points)
+        )
+    }
+}
+
+/** Properties of every key-dependent S-Box `S0 ^ c`
+ *
+ * XOR with a constant after the S-Box leaves every output difference
+ * unchanged, so the DDT and the differential uniformity are the same for
+ * all `c`. It flips the sign of `lat[a][b]` when `b·c` is odd, so `|lat|`
+ * and the nonlinearity are kept, and it only changes the constant term of
+ * the ANF, so the degree is kept. Fixed points and opposite fixed points
+ * are not preserved and are listed per constant. The digest is a product of
+ * non-zero field elements, so `c == 0` never occurs in a round.
+ */
+#[derive(Debug, Clone)]
+pub struct KeyedSummary {
+    /** Summary of `S0` itself */
+    pub base: Summary,
+    /** `DDT(S0 ^ c) == DDT(S0)` for every `c` */
+    pub ddt_preserved: bool,
+    /** `|LAT(S0 ^ c)| == |LAT(S0)|` for every `c` */
+    pub lat_magnitude_preserved: bool,
+    /** `deg(S0 ^ c) == deg(S0)` for every `c` */
+    pub degree_preserved: bool,
+    /** Number of fixed points of `S0 ^ c`, indexed by `c` */
+    pub fixed_points: [u16; 256],
+    /** Number of opposite fixed points of `S0 ^ c`, indexed by `c` */
+    pub opposite_fixed_points: [u16; 256],
+}
+
+impl KeyedSummary {
+    /** Check every constant exhaustively, which takes a moment in debug builds */
+    pub fn new(s0: &SBox) -> Self {
+        let base = Summary::new(s0);
+        let base_ddt = ddt(s0);
+        let base_lat = lat(s0);
+        let mut summary = Self {
+            base,
+            ddt_preserved: true,
+            lat_magnitude_preserved: true,
+            degree_preserved: true,
+            fixed_points: [0; 256],
+            opposite_fixed_points: [0; 256],
+        };
+
+        for c in 0..=255 {
+            let s_box = keyed(s0, c);
+
+            summary.ddt_preserved &= *ddt(&s_box) == *base_ddt;
+            summary.lat_magnitude_preserved &= lat(&s_box)
+                .iter()
+                .flatten()
+                .zip(base_lat.iter().flatten())
+                .all(|(a, b)| a.abs() == b.abs());
+            summary.degree_preserved &= algebraic_degree(&s_box) == summary.base.algebraic_degree;
+            summary.fixed_points[c as usize] = fixed_points(&s_box).len() as u16;
+            summary.opposite_fixed_points[c as usize] = opposite_fixed_points(&s_box).len() as u16;
+        }
+
+        summary
+    }
+}
+
+impl fmt::Display for KeyedSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let preserved = |kept: bool| if kept { "preserved" } else { "NOT preserved" };
+        let range = |counts: &[u16; 256]| {
+            let counts = &counts[1..];
+            let max = *counts.iter().max().unwrap();
+            let worst = counts.iter().position(|&n| n == max).unwrap() + 1;
+            format!(
+                "{} to {} per constant, most with c = {worst:02x}, none for {} constants",
+                counts.iter().min().unwrap(),
+                max,
+                counts.iter().filter(|&&n| n == 0).count()
+            )
+        };
+
+        writeln!(f, "S0")?;
+        write!(f, "{}", self.base)?;
+        writeln!(f)?;
+        writeln!(f, "S0 ^ c for c in 01..ff")?;
+        writeln!(
+            f,
+            "DDT:                     {}",
+            preserved(self.ddt_preserved)
+        )?;
+        writeln!(
+            f,
+            "|LAT|:                   {}",
+            preserved(self.lat_magnitude_preserved)
+        )?;
+        writeln!(
+            f,
+            "algebraic degree:        {}",
+            preserved(self.degree_preserved)
+        )?;
+        writeln!(f, "fixed points:            {}", range(&self.fixed_points))?;
+        writeln!(
+            f,
+            "opposite fixed points:   {}",
+            range(&self.opposite_fixed_points)
+        )
+    }
+}
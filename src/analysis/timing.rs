@@ -0,0 +1,320 @@
+/*!
+ * Timing leakage measurement in the style of dudect
+ *
+ * An operation is timed on inputs of two classes, one fixed value and
+ * uniformly random values, in a random interleaved order. Welch's t-test
+ * then tells whether the two timing distributions differ. It is repeated on
+ * measurements cropped at several percentiles, as the upper tail is mostly
+ * noise from interrupts and frequency scaling. A large `|t|` is evidence of
+ * leakage, a small one only means none was found with this many samples.
+ */
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::modes::BlockCipher;
+use crate::reference::Cipher;
+use crate::rng::SplitMix64;
+
+/** `|t|` above which the two classes are considered to differ */
+pub const THRESHOLD: f64 = 4.5;
+
+/** Percentiles the measurements are cropped at, besides the uncropped test */
+const CROPS: [f64; 5] = [0.5, 0.75, 0.9, 0.95, 0.99];
+
+/** Running mean and variance of both classes for Welch's t-test */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Welch {
+    count: [f64; 2],
+    mean: [f64; 2],
+    /** Sum of squared differences from the mean */
+    m2: [f64; 2],
+}
+
+impl Welch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /** Add a sample to class 0 or 1 */
+    pub fn push(&mut self, class: usize, x: f64) {
+        self.count[class] += 1.0;
+        let delta = x - self.mean[class];
+        self.mean[class] += delta / self.count[class];
+        self.m2[class] += delta * (x - self.mean[class]);
+    }
+    pub fn count(&self, class: usize) -> usize {
+        self.count[class] as usize
+    }
+    pub fn mean(&self, class: usize) -> f64 {
+        self.mean[class]
+    }
+    /** Welch's t-statistic, 0 while a class has fewer than two samples */
+    pub fn t(&self) -> f64 {
+        if self.count.iter().any(|&n| n < 2.0) {
+            return 0.0;
+        }
+        let variance = |class: usize| self.m2[class] / (self.count[class] - 1.0);
+        let error = (variance(0) / self.count[0] + variance(1) / self.count[1]).sqrt();
+        if error == 0.0 {
+            return 0.0;
+        }
+
+        (self.mean[0] - self.mean[1]) / error
+    }
+}
+
+/** Outcome of timing one operation */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leakage {
+    pub samples: usize,
+    /** Mean time of the fixed and the random class in nanoseconds */
+    pub mean: [f64; 2],
+    /** t-statistic over all measurements */
+    pub t: f64,
+    /** Largest `|t|` over the uncropped and every cropped test */
+    pub max_t: f64,
+}
+
+impl Leakage {
+    pub fn leaks(&self) -> bool {
+        self.max_t > THRESHOLD
+    }
+}
+
+/** Time `run` on `samples` inputs, `input(false, rng)` builds the fixed class and `input(true, rng)` the random one
+ *
+ * Inputs and classes are drawn before timing starts, so only `run` is measured.
+ */
+pub fn measure<I>(
+    samples: usize,
+    seed: u64,
+    mut input: impl FnMut(bool, &mut SplitMix64) -> I,
+    mut run: impl FnMut(&I),
+) -> Leakage {
+    assert!(samples >= 4, "at least 4 samples are required");
+    let mut rng = SplitMix64::new(seed);
+    let classes: Vec<usize> = (0..samples)
+        .map(|_| (rng.next_u64() & 1) as usize)
+        .collect();
+    let inputs: Vec<I> = classes
+        .iter()
+        .map(|&class| input(class == 1, &mut rng))
+        .collect();
+
+    let times: Vec<f64> = inputs
+        .iter()
+        .map(|input| {
+            let start = Instant::now();
+            run(black_box(input));
+            start.elapsed().as_nanos() as f64
+        })
+        .collect();
+
+    let mut sorted = times.clone();
+    sorted.sort_by(f64::total_cmp);
+    let limits = CROPS.map(|p| sorted[((samples - 1) as f64 * p) as usize]);
+
+    let mut all = Welch::new();
+    let mut cropped = vec![Welch::new(); limits.len()];
+    for (&class, &time) in classes.iter().zip(&times) {
+        all.push(class, time);
+        for (welch, &limit) in cropped.iter_mut().zip(&limits) {
+            if time <= limit {
+                welch.push(class, time);
+            }
+        }
+    }
+
+    Leakage {
+        samples,
+        mean: [all.mean(0), all.mean(1)],
+        t: all.t(),
+        max_t: cropped
+            .iter()
+            .map(|welch| welch.t().abs())
+            .fold(all.t().abs(), f64::max),
+    }
+}
+
+fn random_bytes(rng: &mut SplitMix64, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/** Time `operation` under one random key on a fixed against random blocks of `len` bytes */
+fn blocks<T>(
+    len: usize,
+    samples: usize,
+    seed: u64,
+    new: impl FnOnce(&[u8]) -> T,
+    operation: impl Fn(&T, &mut Vec<u8>),
+) -> Leakage {
+    let mut rng = SplitMix64::new(seed ^ 1);
+    let cipher = new(&random_bytes(&mut rng, len));
+    let fixed = random_bytes(&mut rng, len);
+
+    measure(
+        samples,
+        seed,
+        |random, rng| match random {
+            true => random_bytes(rng, len),
+            false => fixed.clone(),
+        },
+        |block| {
+            let mut block = block.clone();
+            operation(&cipher, &mut block);
+            black_box(block);
+        },
+    )
+}
+
+/** Time the key setup `new` on a fixed against random keys of `len` bytes */
+fn keys<T>(len: usize, samples: usize, seed: u64, new: impl Fn(&[u8]) -> T) -> Leakage {
+    let mut rng = SplitMix64::new(seed ^ 1);
+    let fixed = random_bytes(&mut rng, len);
+
+    measure(
+        samples,
+        seed,
+        |random, rng| match random {
+            true => random_bytes(rng, len),
+            false => fixed.clone(),
+        },
+        |key| {
+            black_box(new(key));
+        },
+    )
+}
+
+/** Time `encrypt_block` of one random key on a fixed against random blocks */
+pub fn encrypt<C: BlockCipher>(samples: usize, seed: u64) -> Leakage {
+    blocks(
+        C::BLOCK_SIZE,
+        samples,
+        seed,
+        C::new_from_slice,
+        |cipher, block| cipher.encrypt_block(block),
+    )
+}
+
+/** Time `decrypt_block` of one random key on a fixed against random blocks */
+pub fn decrypt<C: BlockCipher>(samples: usize, seed: u64) -> Leakage {
+    blocks(
+        C::BLOCK_SIZE,
+        samples,
+        seed,
+        C::new_from_slice,
+        |cipher, block| cipher.decrypt_block(block),
+    )
+}
+
+/** Time the key schedule on a fixed against random keys */
+pub fn key_schedule<C: BlockCipher>(samples: usize, seed: u64) -> Leakage {
+    keys(C::BLOCK_SIZE, samples, seed, C::new_from_slice)
+}
+
+/** Name of the GF(2^8) and S-Box implementation compiled into `Cipher*` */
+pub fn backend() -> &'static str {
+    if cfg!(feature = "poc") {
+        "poc, GF(2^8) arithmetic on the fly"
+    } else if cfg!(feature = "compact") {
+        "compact, GF28_LOG, GF28_EXP, GF28_INV and S0 lookup tables"
+    } else {
+        "GF28_TABLE, GF28_INV and S0 lookup tables"
+    }
+}
+
+/** Backend of `reference::Cipher`, which is compiled into every build */
+pub const REFERENCE_BACKEND: &str = "reference::Cipher, GF(2^8) arithmetic on the fly";
+
+/** The operations of `encrypt`, `decrypt` and `key_schedule`, timed on `reference::Cipher` */
+pub fn reference(len: usize, samples: usize, seed: u64) -> Vec<(&'static str, Leakage)> {
+    vec![
+        (
+            "encrypt",
+            blocks(len, samples, seed, Cipher::new, |cipher, block| {
+                *block = cipher.encrypt(block)
+            }),
+        ),
+        (
+            "decrypt",
+            blocks(len, samples, seed, Cipher::new, |cipher, block| {
+                *block = cipher.decrypt(block)
+            }),
+        ),
+        ("new", keys(len, (samples / 10).max(4), seed, Cipher::new)),
+    ]
+}
+
+/** Every operation of one variant, printed by `narrowway analyze timing`
+ *
+ * Times `Cipher*` with the backend of this build and `reference::Cipher`
+ * side by side. Both look up the round S-Boxes during encryption and
+ * decryption, the backends differ in how the key setup computes them.
+ */
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub bits: usize,
+    pub backend: &'static str,
+    pub operations: Vec<(&'static str, Leakage)>,
+    /** The same operations on `reference::Cipher` */
+    pub reference: Vec<(&'static str, Leakage)>,
+}
+
+impl Report {
+    /** `samples` timings of every block operation and a tenth of them of the key schedule */
+    pub fn new<C: BlockCipher>(samples: usize, seed: u64) -> Self {
+        Self {
+            bits: C::BLOCK_SIZE * 8,
+            backend: backend(),
+            operations: vec![
+                ("encrypt", encrypt::<C>(samples, seed)),
+                ("decrypt", decrypt::<C>(samples, seed)),
+                ("new", key_schedule::<C>((samples / 10).max(4), seed)),
+            ],
+            reference: reference(C::BLOCK_SIZE, samples, seed),
+        }
+    }
+}
+
+fn write_operations(
+    f: &mut fmt::Formatter<'_>,
+    backend: &str,
+    operations: &[(&str, Leakage)],
+) -> fmt::Result {
+    writeln!(f, "backend: {backend}")?;
+    writeln!(
+        f,
+        "operation  samples  fixed ns  random ns        t    max |t|  verdict"
+    )?;
+    for (name, leakage) in operations {
+        writeln!(
+            f,
+            "{:<9}  {:>7}  {:>8.0}  {:>9.0}  {:>7.2}  {:>9.2}  {}",
+            name,
+            leakage.samples,
+            leakage.mean[0],
+            leakage.mean[1],
+            leakage.t,
+            leakage.max_t,
+            if leakage.leaks() {
+                "LEAKS"
+            } else {
+                "no leak found"
+            }
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "NarrowWay-{}, fixed against random inputs", self.bits)?;
+        writeln!(f)?;
+        write_operations(f, self.backend, &self.operations)?;
+        writeln!(f)?;
+        write_operations(f, REFERENCE_BACKEND, &self.reference)
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use narrowway::Mode;
+use narrowway::container::{MAX_CHUNK_SIZE, Variant};
+
+/** Options shared by all subcommands, unused ones are rejected by the subcommand */
+#[derive(Debug, Default)]
+pub struct Options {
+    pub variant: Option<Variant>,
+    pub mode: Option<Mode>,
+    pub key_file: Option<String>,
+    /** Hex encoded key given on the command line */
+    pub key: Option<String>,
+    pub passphrase_file: Option<String>,
+    pub passphrase_env: Option<String>,
+    pub iterations: Option<u32>,
+    pub chunk_size: Option<u32>,
+    /** Entries of the Random KAT files */
+    pub entries: Option<usize>,
+    /** Samples of analyze avalanche and related */
+    pub samples: Option<usize>,
+    /** Bits per stream of analyze randomness */
+    pub bits: Option<usize>,
+    /** Timings of analyze timing */
+    pub timings: Option<usize>,
+    /** Rounds searched exactly by analyze trails */
+    pub rounds: Option<usize>,
+    pub seed: Option<u64>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    /** Arguments which are not options */
+    pub positional: Vec<String>,
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {option}"))
+}
+
+/** Like `parse_number`, but values outside `range` are rejected too */
+fn parse_in<T: FromStr + PartialOrd + Display>(
+    option: &str,
+    value: &str,
+    range: RangeInclusive<T>,
+) -> Result<T, String> {
+    let number = parse_number(option, value)?;
+    match range.contains(&number) {
+        true => Ok(number),
+        false => Err(format!(
+            "{option} must be between {} and {}",
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            options.positional.push(arg.clone());
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {arg}"))?
+            .clone();
+
+        match arg.as_str() {
+            "-v" | "--variant" => {
+                let bits = parse_number(arg, &value)?;
+                options.variant = Some(
+                    Variant::from_bits(bits).ok_or_else(|| format!("unknown variant {value}"))?,
+                );
+            }
+            "-m" | "--mode" => {
+                options.mode =
+                    Some(Mode::from_name(&value).ok_or_else(|| format!("unknown mode {value}"))?);
+            }
+            "-k" | "--key-file" => options.key_file = Some(value),
+            "--key" => options.key = Some(value),
+            "--passphrase-file" => options.passphrase_file = Some(value),
+            "--passphrase-env" => options.passphrase_env = Some(value),
+            "--iterations" => options.iterations = Some(parse_in(arg, &value, 1..=u32::MAX)?),
+            "--chunk-size" => options.chunk_size = Some(parse_in(arg, &value, 1..=MAX_CHUNK_SIZE)?),
+            "--entries" => options.entries = Some(parse_number(arg, &value)?),
+            "--samples" => options.samples = Some(parse_number(arg, &value)?),
+            "--bits" => options.bits = Some(parse_number(arg, &value)?),
+            "--timings" => options.timings = Some(parse_number(arg, &value)?),
+            "--rounds" => options.rounds = Some(parse_number(arg, &value)?),
+            "--seed" => options.seed = Some(parse_number(arg, &value)?),
+            "-i" | "--input" => options.input = Some(value),
+            "-o" | "--output" => options.output = Some(value),
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+
+    Ok(options)
+}
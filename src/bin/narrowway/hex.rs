@@ -0,0 +1,22 @@
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/** Decode a hex string, an optional `0x` prefix and whitespace are ignored */
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .trim_start_matches("0x")
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+
+    /* `from_str_radix` would also take a sign */
+    if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
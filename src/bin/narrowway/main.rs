@@ -0,0 +1,594 @@
+mod args;
+mod hex;
+mod vectors;
+
+use args::Options;
+use narrowway::analysis::avalanche::{self, Input};
+use narrowway::analysis::{diffusion, keys, randomness, related, sbox, timing, trail};
+use narrowway::container::{self, Header, Kdf, Reader, Variant, Writer};
+use narrowway::error::Error;
+use narrowway::stream::{DecryptReader, EncryptWriter};
+use narrowway::trace::Traceable;
+use narrowway::{BlockCipher, Cipher256, Cipher384, Cipher512, KeyInit, Mode, kdf};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use vectors::{Kat, Mct};
+
+const USAGE: &str = "\
+Usage: narrowway <command> [options]
+
+Commands:
+  encrypt   encrypt INPUT into OUTPUT
+  decrypt   decrypt INPUT into OUTPUT
+  keygen    write a random hex key to OUTPUT
+  info      print the header of an encrypted file
+  block     <encrypt|decrypt> <HEX>: transform a single hex encoded block
+  vectors   examples: print doc/Examples.typ to OUTPUT
+            kat: write VarKey, VarTxt and Random .rsp files into OUTPUT directory
+            mct: write ECB and CBC Monte Carlo .rsp files into OUTPUT directory
+  analyze   sbox: print the properties of S0 and of every S0 ^ c
+            diffusion: print the branch numbers of F and the full-diffusion rounds
+            avalanche: print avalanche, SAC and BIC statistics per round count
+            trails: print the fewest active S-Boxes and characteristic bounds
+            keys: print the collision classes of the S-Box digest, and the
+            weak patterns of the --key-file key if one is given
+            related: print related-key differences and the rounds after
+            which slid keys part
+            randomness: run NIST SP 800-22 tests on CTR keystream and P ^ C
+            for the full cipher and the first three rounds
+            timing: Welch t-test of encrypt, decrypt and new timings on
+            fixed against random inputs and keys, for this build and for
+            reference::Cipher side by side
+
+Options:
+  -v, --variant <256|384|512>   block and key size, default 256
+  -m, --mode <MODE>             ctr-cmac (default, container format), ctr or cbc
+  -k, --key-file <FILE>         hex encoded key
+      --key <HEX>               hex encoded key, for block only
+      --passphrase-file <FILE>  read the passphrase from the first line of FILE
+      --passphrase-env <VAR>    read the passphrase from environment variable VAR
+      --iterations <N>          PBKDF iterations for new files, at least 1
+      --chunk-size <N>          plaintext bytes per chunk for new files,
+                                1 to 16777216
+  -i, --input <FILE>            default: standard input
+  -o, --output <FILE>           default: standard output
+      --entries <N>             entries of the Random KAT files, default 100
+      --samples <N>             samples of analyze avalanche and related,
+                                default 128
+      --bits <N>                bits per stream of analyze randomness,
+                                default 1048576
+      --timings <N>             timings of analyze timing, default 100000
+      --rounds <N>              rounds searched exactly by analyze trails,
+                                default 4
+      --seed <N>                seed of the Random KAT, Monte Carlo and analysis
+                                samples, default 0
+
+ctr and cbc write the IV followed by unauthenticated ciphertext and only
+accept key files, decrypting them needs the same --variant and --mode.
+
+Exit status:
+  0  success
+  1  invalid command line
+  2  I/O error
+  3  authentication failed: tampered, truncated or wrong key
+  4  malformed or unsupported input
+";
+
+const EXIT_USAGE: u8 = 1;
+const EXIT_IO: u8 = 2;
+const EXIT_AUTHENTICATION: u8 = 3;
+const EXIT_FORMAT: u8 = 4;
+
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Crypto(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        Self::Crypto(err)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Self {
+        Self::Crypto(err.into())
+    }
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => EXIT_USAGE,
+            Self::Crypto(Error::Io(_)) => EXIT_IO,
+            Self::Crypto(
+                Error::Authentication | Error::Padding | Error::Truncated | Error::WrongKey,
+            ) => EXIT_AUTHENTICATION,
+            Self::Crypto(_) => EXIT_FORMAT,
+        }
+    }
+}
+
+fn usage(message: impl Into<String>) -> Failure {
+    Failure::Usage(message.into())
+}
+
+fn open_input(options: &Options) -> io::Result<Box<dyn Read>> {
+    match options.input.as_deref() {
+        None | Some("-") => Ok(Box::new(io::stdin().lock())),
+        Some(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+    }
+}
+
+fn open_output(options: &Options) -> io::Result<Box<dyn Write>> {
+    match options.output.as_deref() {
+        None | Some("-") => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
+    }
+}
+
+/** Bytes from the random number generator of the OS, on every platform `getrandom` supports */
+fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    getrandom::fill(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_key_file<C: BlockCipher>(path: &str) -> Result<Vec<u8>, Failure> {
+    let text = std::fs::read_to_string(path)?;
+
+    match hex::decode(text.trim()) {
+        Some(key) if key.len() == C::BLOCK_SIZE => Ok(key),
+        _ => Err(usage(format!(
+            "key file must hold {} hex digits",
+            2 * C::BLOCK_SIZE
+        ))),
+    }
+}
+
+fn read_passphrase(options: &Options) -> Result<Option<Vec<u8>>, Failure> {
+    if let Some(path) = &options.passphrase_file {
+        let text = std::fs::read(path)?;
+        let line = text.split(|byte| *byte == b'\n').next().unwrap_or(&[]);
+        return Ok(Some(line.strip_suffix(b"\r").unwrap_or(line).to_vec()));
+    }
+    if let Some(var) = &options.passphrase_env {
+        let value = std::env::var_os(var)
+            .ok_or_else(|| usage(format!("environment variable {var} is not set")))?;
+        return Ok(Some(value.into_encoded_bytes()));
+    }
+
+    Ok(None)
+}
+
+fn check_single_secret(options: &Options) -> Result<(), Failure> {
+    let count = [
+        options.key_file.is_some(),
+        options.passphrase_file.is_some(),
+        options.passphrase_env.is_some(),
+    ]
+    .into_iter()
+    .filter(|given| *given)
+    .count();
+
+    if count != 1 {
+        return Err(usage(
+            "exactly one of --key-file, --passphrase-file and --passphrase-env is required",
+        ));
+    }
+    Ok(())
+}
+
+fn encrypt<C: BlockCipher>(options: &Options, mode: Mode) -> Result<(), Failure> {
+    check_single_secret(options)?;
+    let mut input = open_input(options)?;
+    let mut output = open_output(options)?;
+
+    if mode != Mode::CtrCmac {
+        let path = options
+            .key_file
+            .as_deref()
+            .ok_or_else(|| usage(format!("{} mode needs --key-file", mode.name())))?;
+        let key = read_key_file::<C>(path)?;
+        let iv = random_bytes(C::BLOCK_SIZE)?;
+
+        output.write_all(&iv)?;
+        let mut writer = EncryptWriter::new(C::new_from_slice(&key), mode, &iv, output);
+        io::copy(&mut input, &mut writer)?;
+        writer.finish()?.flush()?;
+        return Ok(());
+    }
+
+    let (key, kdf) = match read_passphrase(options)? {
+        Some(passphrase) => {
+            let kdf = Kdf::Pbkdf {
+                iterations: options.iterations.unwrap_or(kdf::DEFAULT_ITERATIONS),
+                salt: random_bytes(container::SALT_SIZE)?.try_into().unwrap(),
+            };
+            (kdf.derive_key::<C>(&passphrase)?, kdf)
+        }
+        None => (
+            read_key_file::<C>(options.key_file.as_deref().unwrap())?,
+            Kdf::Raw,
+        ),
+    };
+    let nonce = random_bytes(C::BLOCK_SIZE - 16)?;
+    let chunk_size = options.chunk_size.unwrap_or(container::DEFAULT_CHUNK_SIZE);
+
+    let mut writer = Writer::<C, _>::new(&key, kdf, &nonce, chunk_size, output)?;
+    io::copy(&mut input, &mut writer)?;
+    writer.finish()?.flush()?;
+
+    Ok(())
+}
+
+fn decrypt_stream<C: BlockCipher>(options: &Options, mode: Mode) -> Result<(), Failure> {
+    let path = options
+        .key_file
+        .as_deref()
+        .ok_or_else(|| usage(format!("{} mode needs --key-file", mode.name())))?;
+    let key = read_key_file::<C>(path)?;
+    let mut input = open_input(options)?;
+    let mut output = open_output(options)?;
+
+    let mut iv = vec![0; C::BLOCK_SIZE];
+    input.read_exact(&mut iv).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Truncated,
+        _ => err.into(),
+    })?;
+
+    let mut reader = DecryptReader::new(C::new_from_slice(&key), mode, &iv, input);
+    io::copy(&mut reader, &mut output)?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn decrypt_container<C: BlockCipher>(
+    options: &Options,
+    header: Header,
+    input: Box<dyn Read>,
+) -> Result<(), Failure> {
+    let key = match &header.kdf {
+        Kdf::Raw => {
+            let path = options
+                .key_file
+                .as_deref()
+                .ok_or_else(|| usage("this file needs --key-file"))?;
+            read_key_file::<C>(path)?
+        }
+        kdf => {
+            let passphrase = read_passphrase(options)?
+                .ok_or_else(|| usage("this file needs --passphrase-file or --passphrase-env"))?;
+            kdf.derive_key::<C>(&passphrase)?
+        }
+    };
+
+    let mut reader = Reader::<C, _>::new(header, &key, input)?;
+    let mut output = open_output(options)?;
+    io::copy(&mut reader, &mut output)?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn decrypt(options: &Options) -> Result<(), Failure> {
+    let variant = options.variant.unwrap_or(Variant::Nw256);
+
+    match options.mode.unwrap_or(Mode::CtrCmac) {
+        Mode::CtrCmac => {
+            let mut input = open_input(options)?;
+            let header = Header::read_from(&mut input)?;
+
+            match header.variant {
+                Variant::Nw256 => decrypt_container::<Cipher256>(options, header, input),
+                Variant::Nw384 => decrypt_container::<Cipher384>(options, header, input),
+                Variant::Nw512 => decrypt_container::<Cipher512>(options, header, input),
+            }
+        }
+        mode => match variant {
+            Variant::Nw256 => decrypt_stream::<Cipher256>(options, mode),
+            Variant::Nw384 => decrypt_stream::<Cipher384>(options, mode),
+            Variant::Nw512 => decrypt_stream::<Cipher512>(options, mode),
+        },
+    }
+}
+
+/** Weak patterns of the S-Boxes generated by `key` */
+fn weaknesses(variant: Variant, key: &[u8]) -> Vec<keys::Weakness> {
+    match variant {
+        Variant::Nw256 => keys::weaknesses(Cipher256::new_from_slice(key).digests()),
+        Variant::Nw384 => keys::weaknesses(Cipher384::new_from_slice(key).digests()),
+        Variant::Nw512 => keys::weaknesses(Cipher512::new_from_slice(key).digests()),
+    }
+}
+
+fn keygen(options: &Options) -> Result<(), Failure> {
+    let variant = options.variant.unwrap_or(Variant::Nw256);
+    let mut key = random_bytes(variant.block_size())?;
+    while !weaknesses(variant, &key).is_empty() {
+        key = random_bytes(variant.block_size())?;
+    }
+
+    let mut output = open_output(options)?;
+    writeln!(output, "{}", hex::encode(&key))?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn info(options: &Options) -> Result<(), Failure> {
+    let header = Header::read_from(&mut open_input(options)?)?;
+    let mut output = open_output(options)?;
+
+    writeln!(output, "variant:    NarrowWay-{}", header.variant.bits())?;
+    writeln!(output, "mode:       {}", header.mode.name())?;
+    match &header.kdf {
+        Kdf::Raw => writeln!(output, "kdf:        raw key")?,
+        Kdf::Pbkdf { iterations, salt } => {
+            writeln!(output, "kdf:        pbkdf")?;
+            writeln!(output, "iterations: {iterations}")?;
+            writeln!(output, "salt:       {}", hex::encode(salt))?;
+        }
+    }
+    writeln!(output, "chunk size: {}", header.chunk_size)?;
+    writeln!(output, "key id:     {}", hex::encode(&header.key_id))?;
+    writeln!(output, "nonce:      {}", hex::encode(&header.nonce))?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn block<C: BlockCipher>(options: &Options) -> Result<(), Failure> {
+    let key = match (&options.key, &options.key_file) {
+        (Some(key), None) => hex::decode(key)
+            .filter(|key| key.len() == C::BLOCK_SIZE)
+            .ok_or_else(|| usage(format!("--key must be {} hex digits", 2 * C::BLOCK_SIZE)))?,
+        (None, Some(path)) => read_key_file::<C>(path)?,
+        _ => return Err(usage("exactly one of --key and --key-file is required")),
+    };
+
+    let [operation, block] = options.positional.as_slice() else {
+        return Err(usage("block needs an operation and a hex block"));
+    };
+    let mut block = hex::decode(block)
+        .filter(|block| block.len() == C::BLOCK_SIZE)
+        .ok_or_else(|| usage(format!("block must be {} hex digits", 2 * C::BLOCK_SIZE)))?;
+
+    let cipher = C::new_from_slice(&key);
+    match operation.as_str() {
+        "encrypt" => cipher.encrypt_block(&mut block),
+        "decrypt" => cipher.decrypt_block(&mut block),
+        _ => return Err(usage(format!("unknown block operation {operation}"))),
+    }
+
+    let mut output = open_output(options)?;
+    writeln!(output, "{}", hex::encode(&block))?;
+    output.flush()?;
+
+    Ok(())
+}
+
+fn write_kat_files(options: &Options) -> Result<(), Failure> {
+    let dir = Path::new(options.output.as_deref().unwrap_or("."));
+    std::fs::create_dir_all(dir)?;
+
+    let kinds = [
+        Kat::VarKey,
+        Kat::VarTxt,
+        Kat::Random {
+            count: options.entries.unwrap_or(100),
+            seed: options.seed.unwrap_or(0),
+        },
+    ];
+    for variant in Variant::ALL {
+        for kind in kinds {
+            let path = dir.join(format!("NW{}{}.rsp", variant.bits(), kind.name()));
+            let mut output = BufWriter::new(File::create(path)?);
+            match variant {
+                Variant::Nw256 => vectors::write_kat::<Cipher256>(kind, &mut output)?,
+                Variant::Nw384 => vectors::write_kat::<Cipher384>(kind, &mut output)?,
+                Variant::Nw512 => vectors::write_kat::<Cipher512>(kind, &mut output)?,
+            }
+            output.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_mct_files(options: &Options) -> Result<(), Failure> {
+    let dir = Path::new(options.output.as_deref().unwrap_or("."));
+    std::fs::create_dir_all(dir)?;
+    let seed = options.seed.unwrap_or(0);
+
+    for variant in Variant::ALL {
+        for mode in [Mct::Ecb, Mct::Cbc] {
+            let path = dir.join(format!("NW{}{}MCT.rsp", variant.bits(), mode.name()));
+            let mut output = BufWriter::new(File::create(path)?);
+            match variant {
+                Variant::Nw256 => vectors::write_mct::<Cipher256>(mode, seed, &mut output)?,
+                Variant::Nw384 => vectors::write_mct::<Cipher384>(mode, seed, &mut output)?,
+                Variant::Nw512 => vectors::write_mct::<Cipher512>(mode, seed, &mut output)?,
+            }
+            output.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_vectors(options: &Options) -> Result<(), Failure> {
+    match options.positional.first().map(String::as_str) {
+        Some("examples") => {
+            let mut output = open_output(options)?;
+            writeln!(output, "= Examples")?;
+            vectors::write_example::<Cipher256>(&mut output)?;
+            writeln!(output)?;
+            vectors::write_example::<Cipher384>(&mut output)?;
+            writeln!(output)?;
+            vectors::write_example::<Cipher512>(&mut output)?;
+            output.flush()?;
+            Ok(())
+        }
+        Some("kat") => write_kat_files(options),
+        Some("mct") => write_mct_files(options),
+        _ => Err(usage("vectors needs one of examples, kat and mct")),
+    }
+}
+
+fn avalanche_report<C: Traceable>(options: &Options) -> String {
+    let samples = options.samples.unwrap_or(128);
+    let seed = options.seed.unwrap_or(0);
+
+    [Input::Plaintext, Input::Key]
+        .map(|input| avalanche::Report::new::<C>(input, samples, seed).to_string())
+        .join("\n")
+}
+
+fn analyze(options: &Options) -> Result<(), Failure> {
+    let report = match options.positional.first().map(String::as_str) {
+        Some("sbox") => sbox::KeyedSummary::new(&narrowway::S0).to_string(),
+        Some("diffusion") => diffusion::Report::new().to_string(),
+        Some("avalanche") => match options.variant.unwrap_or(Variant::Nw256) {
+            Variant::Nw256 => avalanche_report::<Cipher256>(options),
+            Variant::Nw384 => avalanche_report::<Cipher384>(options),
+            Variant::Nw512 => avalanche_report::<Cipher512>(options),
+        },
+        Some("trails") => {
+            let variant = options.variant.unwrap_or(Variant::Nw256);
+            let rounds = match variant {
+                Variant::Nw256 => Cipher256::ROUNDS,
+                Variant::Nw384 => Cipher384::ROUNDS,
+                Variant::Nw512 => Cipher512::ROUNDS,
+            };
+            let searched = options.rounds.unwrap_or(4);
+            trail::Report::new(variant.block_size() / 8, rounds, searched).to_string()
+        }
+        Some("related") => {
+            let samples = options.samples.unwrap_or(128);
+            let seed = options.seed.unwrap_or(0);
+            match options.variant.unwrap_or(Variant::Nw256) {
+                Variant::Nw256 => related::Report::new::<Cipher256>(samples, seed).to_string(),
+                Variant::Nw384 => related::Report::new::<Cipher384>(samples, seed).to_string(),
+                Variant::Nw512 => related::Report::new::<Cipher512>(samples, seed).to_string(),
+            }
+        }
+        Some("randomness") => {
+            let bits = options.bits.unwrap_or(1 << 20);
+            let seed = options.seed.unwrap_or(0);
+            if bits < 1 << 12 {
+                return Err(usage("analyze randomness needs at least 4096 bits"));
+            }
+            match options.variant.unwrap_or(Variant::Nw256) {
+                Variant::Nw256 => randomness::Report::new::<Cipher256>(bits, 3, seed).to_string(),
+                Variant::Nw384 => randomness::Report::new::<Cipher384>(bits, 3, seed).to_string(),
+                Variant::Nw512 => randomness::Report::new::<Cipher512>(bits, 3, seed).to_string(),
+            }
+        }
+        Some("timing") => {
+            let samples = options.timings.unwrap_or(100_000);
+            let seed = options.seed.unwrap_or(0);
+            if samples < 40 {
+                return Err(usage("analyze timing needs at least 40 timings"));
+            }
+            match options.variant.unwrap_or(Variant::Nw256) {
+                Variant::Nw256 => timing::Report::new::<Cipher256>(samples, seed).to_string(),
+                Variant::Nw384 => timing::Report::new::<Cipher384>(samples, seed).to_string(),
+                Variant::Nw512 => timing::Report::new::<Cipher512>(samples, seed).to_string(),
+            }
+        }
+        Some("keys") => {
+            let mut report = keys::Classes::new(8).to_string();
+            if let Some(path) = &options.key_file {
+                let variant = options.variant.unwrap_or(Variant::Nw256);
+                let key = match variant {
+                    Variant::Nw256 => read_key_file::<Cipher256>(path)?,
+                    Variant::Nw384 => read_key_file::<Cipher384>(path)?,
+                    Variant::Nw512 => read_key_file::<Cipher512>(path)?,
+                };
+                let found = weaknesses(variant, &key);
+
+                report.push('\n');
+                if found.is_empty() {
+                    report.push_str("key: no known weak pattern\n");
+                }
+                for weakness in found {
+                    report.push_str(&format!("key: {weakness}\n"));
+                }
+            }
+            report
+        }
+        _ => {
+            return Err(usage(
+                "analyze needs one of sbox, diffusion, avalanche, trails, keys, related, randomness and timing",
+            ));
+        }
+    };
+
+    let mut output = open_output(options)?;
+    output.write_all(report.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let (command, rest) = args.split_first().ok_or_else(|| usage("missing command"))?;
+    let options = args::parse(rest).map_err(Failure::Usage)?;
+
+    if let Some(arg) = options.positional.first()
+        && !matches!(command.as_str(), "block" | "vectors" | "analyze")
+    {
+        return Err(usage(format!("unexpected argument {arg}")));
+    }
+
+    let variant = options.variant.unwrap_or(Variant::Nw256);
+    match command.as_str() {
+        "encrypt" => {
+            let mode = options.mode.unwrap_or(Mode::CtrCmac);
+            match variant {
+                Variant::Nw256 => encrypt::<Cipher256>(&options, mode),
+                Variant::Nw384 => encrypt::<Cipher384>(&options, mode),
+                Variant::Nw512 => encrypt::<Cipher512>(&options, mode),
+            }
+        }
+        "decrypt" => decrypt(&options),
+        "keygen" => keygen(&options),
+        "info" => info(&options),
+        "block" => match variant {
+            Variant::Nw256 => block::<Cipher256>(&options),
+            Variant::Nw384 => block::<Cipher384>(&options),
+            Variant::Nw512 => block::<Cipher512>(&options),
+        },
+        "vectors" => write_vectors(&options),
+        "analyze" => analyze(&options),
+        _ => Err(usage(format!("unknown command {command}"))),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.is_empty() || matches!(args[0].as_str(), "-h" | "--help" | "help") {
+        print!("{USAGE}");
+        return if args.is_empty() {
+            ExitCode::from(EXIT_USAGE)
+        } else {
+            ExitCode::SUCCESS
+        };
+    }
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            match &failure {
+                Failure::Usage(message) => eprintln!("narrowway: {message}\n\n{USAGE}"),
+                Failure::Crypto(err) => eprintln!("narrowway: {err}"),
+            }
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::hex;
+use narrowway::BlockCipher;
+use narrowway::rng::SplitMix64;
+use std::io::{self, Write};
+
+/** Format a hex value the way `doc/Examples.typ` does, 64 digits per line */
+fn wrap_hex(label: &str, bytes: &[u8]) -> String {
+    let prefix = format!("{label} = 0x");
+    let indent = " ".repeat(prefix.len());
+
+    hex::encode(bytes)
+        .as_bytes()
+        .chunks(64)
+        .enumerate()
+        .map(|(i, line)| {
+            let line = std::str::from_utf8(line).unwrap();
+            if i == 0 {
+                format!("{prefix}{line}")
+            } else {
+                format!("\n{indent}{line}")
+            }
+        })
+        .collect()
+}
+
+/** Write one section of `doc/Examples.typ` */
+pub fn write_example<C: BlockCipher>(output: &mut dyn Write) -> io::Result<()> {
+    let size = C::BLOCK_SIZE;
+    let key = vec![0; size];
+    let mut block = vec![0; size];
+
+    writeln!(output, "*NarrowWay-{}*", size * 8)?;
+    writeln!(output)?;
+    writeln!(
+        output,
+        "Assume encrypting an empty block ({size} zero bytes) using an empty key, the encrypted block should be:"
+    )?;
+    writeln!(output)?;
+    writeln!(output, "```")?;
+    writeln!(output, "{}", wrap_hex("K", &key))?;
+    writeln!(output, "{}", wrap_hex("M", &block))?;
+    C::new_from_slice(&key).encrypt_block(&mut block);
+    writeln!(output, "{}", wrap_hex("E(K, M)", &block))?;
+    writeln!(output, "```")
+}
+
+/** Which input varies between the entries of a KAT file */
+#[derive(Debug, Clone, Copy)]
+pub enum Kat {
+    /** The key has its leading `COUNT + 1` bits set, the plaintext is zero */
+    VarKey,
+    /** The plaintext has its leading `COUNT + 1` bits set, the key is zero */
+    VarTxt,
+    Random {
+        count: usize,
+        seed: u64,
+    },
+}
+
+impl Kat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::VarKey => "VarKey",
+            Self::VarTxt => "VarTxt",
+            Self::Random { .. } => "Random",
+        }
+    }
+}
+
+fn leading_ones(size: usize, count: usize) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    for bit in 0..count {
+        bytes[bit / 8] |= 0x80 >> (bit % 8);
+    }
+    bytes
+}
+
+/** Write a known-answer file in the layout of NIST `.rsp` files */
+pub fn write_kat<C: BlockCipher>(kind: Kat, output: &mut dyn Write) -> io::Result<()> {
+    let size = C::BLOCK_SIZE;
+
+    writeln!(
+        output,
+        "# NarrowWay-{} {} known-answer tests",
+        size * 8,
+        kind.name()
+    )?;
+    if let Kat::Random { seed, .. } = kind {
+        writeln!(output, "# SplitMix64 seed {seed}")?;
+    }
+    writeln!(output)?;
+    writeln!(output, "[ENCRYPT]")?;
+
+    let count = match kind {
+        Kat::VarKey | Kat::VarTxt => size * 8,
+        Kat::Random { count, .. } => count,
+    };
+    let mut rng = match kind {
+        Kat::Random { seed, .. } => Some(SplitMix64::new(seed)),
+        _ => None,
+    };
+    let zero_key_cipher = C::new_from_slice(&vec![0; size]);
+
+    for i in 0..count {
+        let (key, plaintext) = match &mut rng {
+            Some(rng) => {
+                let mut key = vec![0; size];
+                let mut plaintext = vec![0; size];
+                rng.fill_bytes(&mut key);
+                rng.fill_bytes(&mut plaintext);
+                (key, plaintext)
+            }
+            None if matches!(kind, Kat::VarKey) => (leading_ones(size, i + 1), vec![0; size]),
+            None => (vec![0; size], leading_ones(size, i + 1)),
+        };
+
+        let mut ciphertext = plaintext.clone();
+        if matches!(kind, Kat::VarTxt) {
+            zero_key_cipher.encrypt_block(&mut ciphertext);
+        } else {
+            C::new_from_slice(&key).encrypt_block(&mut ciphertext);
+        }
+
+        writeln!(output)?;
+        writeln!(output, "COUNT = {i}")?;
+        writeln!(output, "KEY = {}", hex::encode(&key))?;
+        writeln!(output, "PLAINTEXT = {}", hex::encode(&plaintext))?;
+        writeln!(output, "CIPHERTEXT = {}", hex::encode(&ciphertext))?;
+    }
+
+    Ok(())
+}
+
+/** Monte Carlo test in the style of AESAVS
+ *
+ * Every outer iteration runs 1000 chained block operations, then XORs the
+ * last output into the key. Only the first 10 and every 100th outer
+ * iteration are written as checkpoints.
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum Mct {
+    Ecb,
+    Cbc,
+}
+
+impl Mct {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ecb => "ECB",
+            Self::Cbc => "CBC",
+        }
+    }
+}
+
+const MCT_OUTER: usize = 1000;
+const MCT_INNER: usize = 1000;
+
+fn is_checkpoint(outer: usize) -> bool {
+    outer < 10 || outer % 100 == 99
+}
+
+fn xor(a: &mut [u8], b: &[u8]) {
+    for (x, y) in a.iter_mut().zip(b) {
+        *x ^= y;
+    }
+}
+
+/** One direction of a Monte Carlo test, `input` is the plaintext when encrypting */
+fn write_mct_section<C: BlockCipher>(
+    mode: Mct,
+    decrypt: bool,
+    rng: &mut SplitMix64,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let size = C::BLOCK_SIZE;
+    let (mut key, mut iv, mut input) = (vec![0; size], vec![0; size], vec![0; size]);
+    rng.fill_bytes(&mut key);
+    if let Mct::Cbc = mode {
+        rng.fill_bytes(&mut iv);
+    }
+    rng.fill_bytes(&mut input);
+
+    let (input_name, output_name) = match decrypt {
+        false => ("PLAINTEXT", "CIPHERTEXT"),
+        true => ("CIPHERTEXT", "PLAINTEXT"),
+    };
+    writeln!(output)?;
+    writeln!(output, "[{}]", if decrypt { "DECRYPT" } else { "ENCRYPT" })?;
+
+    for outer in 0..MCT_OUTER {
+        let cipher = C::new_from_slice(&key);
+        let mut chain = iv.clone();
+        let mut outputs: Vec<Vec<u8>> = Vec::with_capacity(MCT_INNER);
+        let first_input = input.clone();
+
+        for inner in 0..MCT_INNER {
+            let mut block = input.clone();
+            match (mode, decrypt) {
+                (Mct::Ecb, false) => cipher.encrypt_block(&mut block),
+                (Mct::Ecb, true) => cipher.decrypt_block(&mut block),
+                (Mct::Cbc, false) => {
+                    xor(&mut block, &chain);
+                    cipher.encrypt_block(&mut block);
+                    chain = block.clone();
+                }
+                (Mct::Cbc, true) => {
+                    cipher.decrypt_block(&mut block);
+                    xor(&mut block, &chain);
+                    chain = input.clone();
+                }
+            }
+
+            input = match (mode, inner) {
+                (Mct::Ecb, _) => block.clone(),
+                (Mct::Cbc, 0) => iv.clone(),
+                (Mct::Cbc, _) => outputs[inner - 1].clone(),
+            };
+            outputs.push(block);
+        }
+
+        let last = &outputs[MCT_INNER - 1];
+        if is_checkpoint(outer) {
+            writeln!(output)?;
+            writeln!(output, "COUNT = {outer}")?;
+            writeln!(output, "KEY = {}", hex::encode(&key))?;
+            if let Mct::Cbc = mode {
+                writeln!(output, "IV = {}", hex::encode(&iv))?;
+            }
+            writeln!(output, "{input_name} = {}", hex::encode(&first_input))?;
+            writeln!(output, "{output_name} = {}", hex::encode(last))?;
+        }
+
+        xor(&mut key, last);
+        if let Mct::Cbc = mode {
+            iv = last.clone();
+            input = outputs[MCT_INNER - 2].clone();
+        } else {
+            input = last.clone();
+        }
+    }
+
+    Ok(())
+}
+
+/** Write the encryption and decryption Monte Carlo tests of one mode */
+pub fn write_mct<C: BlockCipher>(mode: Mct, seed: u64, output: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        output,
+        "# NarrowWay-{} {} Monte Carlo tests",
+        C::BLOCK_SIZE * 8,
+        mode.name()
+    )?;
+    writeln!(output, "# SplitMix64 seed {seed}")?;
+
+    let mut rng = SplitMix64::new(seed);
+    write_mct_section::<C>(mode, false, &mut rng, output)?;
+    write_mct_section::<C>(mode, true, &mut rng, output)
+}
@@ -0,0 +1,494 @@
+/*!
+ * Authenticated file format
+ *
+ * A file is a header followed by chunks, all integers are big-endian:
+ *
+ * ```text
+ * magic        4   "NWAY"
+ * version      1   1
+ * variant      1   1: NarrowWay-256, 2: NarrowWay-384, 3: NarrowWay-512
+ * mode         1   3: CTR-CMAC
+ * kdf          1   0: raw key, 1: NarrowWay PBKDF
+ * iterations   4   0 for raw keys
+ * salt         16  zero for raw keys
+ * chunk size   4   plaintext bytes per chunk, 1 to 16 MiB
+ * key id       8   leading bytes of E_K("NarrowWay.kid")
+ * nonce        block size - 16
+ * ```
+ *
+ * Every chunk but the last one carries exactly `chunk size` bytes of
+ * plaintext, the last one carries 0 to `chunk size` bytes. A chunk is the
+ * CTR ciphertext followed by a one-block CMAC tag over
+ * `header || chunk IV || ciphertext`, where the chunk IV is
+ * `nonce || u32 index || final flag || 3 zero bytes || u64 block counter`.
+ * Since the index and the final flag are authenticated, reordered, dropped
+ * and truncated chunks are all detected.
+ */
+
+use crate::error::Error;
+use crate::kdf;
+use crate::modes::{
+    BlockCipher, BlockEncrypt, Cmac, KeyInit, Mode, ct_eq, ctr_block, ctr_cmac_ciphers,
+    derive_block,
+};
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"NWAY";
+pub const VERSION: u8 = 1;
+pub const SALT_SIZE: usize = 16;
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+const KEY_ID_LABEL: &[u8] = b"NarrowWay.kid";
+const KDF_RAW: u8 = 0;
+const KDF_PBKDF: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Nw256,
+    Nw384,
+    Nw512,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 3] = [Variant::Nw256, Variant::Nw384, Variant::Nw512];
+
+    pub fn bits(&self) -> usize {
+        self.block_size() * 8
+    }
+    /** Block size in bytes, which is also the key size */
+    pub fn block_size(&self) -> usize {
+        match self {
+            Self::Nw256 => 32,
+            Self::Nw384 => 48,
+            Self::Nw512 => 64,
+        }
+    }
+    pub fn from_bits(bits: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|variant| variant.bits() == bits)
+    }
+    fn code(&self) -> u8 {
+        match self {
+            Self::Nw256 => 1,
+            Self::Nw384 => 2,
+            Self::Nw512 => 3,
+        }
+    }
+    fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|variant| variant.code() == code)
+    }
+}
+
+fn mode_code(mode: Mode) -> u8 {
+    match mode {
+        Mode::Cbc => 1,
+        Mode::Ctr => 2,
+        Mode::CtrCmac => 3,
+    }
+}
+
+/** How the key was obtained */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kdf {
+    /** The key was given directly */
+    Raw,
+    /** The key was derived with [`kdf::pbkdf`] from a passphrase */
+    Pbkdf {
+        iterations: u32,
+        salt: [u8; SALT_SIZE],
+    },
+}
+
+impl Kdf {
+    /** Turn a raw key or a passphrase into the key of the variant `C` */
+    pub fn derive_key<C: BlockCipher>(&self, secret: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Raw if secret.len() == C::BLOCK_SIZE => Ok(secret.to_vec()),
+            Self::Raw => Err(Error::InvalidKeyLength(secret.len())),
+            Self::Pbkdf { iterations, salt } => Ok(kdf::pbkdf::<C>(secret, salt, *iterations)),
+        }
+    }
+    /** Reject parameters which `Header::read_from` would refuse */
+    fn check(&self) -> Result<(), Error> {
+        match self {
+            Self::Pbkdf { iterations: 0, .. } => Err(Error::InvalidHeader("zero KDF iterations")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/** Identifier of a key as stored in the header */
+pub fn key_id<C: BlockEncrypt>(cipher: &C) -> [u8; 8] {
+    derive_block(cipher, KEY_ID_LABEL)[..8].try_into().unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub variant: Variant,
+    pub mode: Mode,
+    pub kdf: Kdf,
+    pub chunk_size: u32,
+    pub key_id: [u8; 8],
+    pub nonce: Vec<u8>,
+}
+
+impl Header {
+    /** Size of the fields before the nonce */
+    const FIXED_SIZE: usize = 40;
+
+    pub fn nonce_size(variant: Variant) -> usize {
+        variant.block_size() - 16
+    }
+    /** Encoded size of a header */
+    pub fn size(variant: Variant) -> usize {
+        Self::FIXED_SIZE + Self::nonce_size(variant)
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::size(self.variant));
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.variant.code());
+        bytes.push(mode_code(self.mode));
+
+        match &self.kdf {
+            Kdf::Raw => {
+                bytes.push(KDF_RAW);
+                bytes.extend_from_slice(&[0; 4 + SALT_SIZE]);
+            }
+            Kdf::Pbkdf { iterations, salt } => {
+                bytes.push(KDF_PBKDF);
+                bytes.extend_from_slice(&iterations.to_be_bytes());
+                bytes.extend_from_slice(salt);
+            }
+        }
+
+        bytes.extend_from_slice(&self.chunk_size.to_be_bytes());
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.nonce);
+
+        bytes
+    }
+    /** Read and validate a header, leaving `reader` at the first chunk */
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let mut fixed = [0; Self::FIXED_SIZE];
+        read_exact(reader, &mut fixed[..4])?;
+        if fixed[..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        read_exact(reader, &mut fixed[4..])?;
+
+        if fixed[4] != VERSION {
+            return Err(Error::UnsupportedVersion(fixed[4]));
+        }
+        let variant = Variant::from_code(fixed[5]).ok_or(Error::UnsupportedVariant(fixed[5]))?;
+        /* only authenticated chunks are defined in version 1 */
+        if fixed[6] != mode_code(Mode::CtrCmac) {
+            return Err(Error::UnsupportedMode(fixed[6]));
+        }
+
+        let iterations = u32::from_be_bytes(fixed[8..12].try_into().unwrap());
+        let salt: [u8; SALT_SIZE] = fixed[12..28].try_into().unwrap();
+        let kdf = match fixed[7] {
+            KDF_RAW if iterations == 0 && salt == [0; SALT_SIZE] => Kdf::Raw,
+            KDF_RAW => return Err(Error::InvalidHeader("raw key with KDF parameters")),
+            KDF_PBKDF if iterations > 0 => Kdf::Pbkdf { iterations, salt },
+            KDF_PBKDF => return Err(Error::InvalidHeader("zero KDF iterations")),
+            kdf => return Err(Error::UnsupportedKdf(kdf)),
+        };
+
+        let chunk_size = u32::from_be_bytes(fixed[28..32].try_into().unwrap());
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(Error::InvalidHeader("chunk size out of range"));
+        }
+
+        let mut nonce = vec![0; Self::nonce_size(variant)];
+        read_exact(reader, &mut nonce)?;
+
+        Ok(Self {
+            variant,
+            mode: Mode::CtrCmac,
+            kdf,
+            chunk_size,
+            key_id: fixed[32..40].try_into().unwrap(),
+            nonce,
+        })
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Truncated,
+        _ => Error::from(err),
+    })
+}
+
+fn variant_of<C: BlockCipher>() -> Variant {
+    Variant::ALL
+        .into_iter()
+        .find(|variant| variant.block_size() == C::BLOCK_SIZE)
+        .unwrap()
+}
+
+/** Chunk encryption shared by the writer and the reader, which only ever encrypts */
+#[derive(Debug)]
+struct ChunkSealer<E> {
+    header: Vec<u8>,
+    nonce: Vec<u8>,
+    ctr: E,
+    mac: Cmac<E>,
+}
+
+impl<E: BlockEncrypt> ChunkSealer<E> {
+    fn new<C: BlockEncrypt>(cipher: &C, header: &Header) -> Self {
+        let (ctr, mac) = ctr_cmac_ciphers(cipher);
+
+        Self {
+            header: header.to_bytes(),
+            nonce: header.nonce.clone(),
+            ctr,
+            mac,
+        }
+    }
+    fn chunk_iv(&self, index: u32, last: bool) -> Vec<u8> {
+        let mut iv = self.nonce.clone();
+        iv.extend_from_slice(&index.to_be_bytes());
+        iv.push(last as u8);
+        iv.resize(E::BLOCK_SIZE, 0);
+        iv
+    }
+    fn apply_keystream(&self, iv: &[u8], data: &mut [u8]) {
+        let mut keystream = vec![0; E::BLOCK_SIZE];
+
+        for (counter, block) in data.chunks_mut(E::BLOCK_SIZE).enumerate() {
+            ctr_block(iv, counter as u64, &mut keystream);
+            self.ctr.encrypt_block(&mut keystream);
+            for (byte, key) in block.iter_mut().zip(&keystream) {
+                *byte ^= key;
+            }
+        }
+    }
+    fn tag(&mut self, iv: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+        self.mac.update(&self.header);
+        self.mac.update(iv);
+        self.mac.update(ciphertext);
+        self.mac.finalize_reset()
+    }
+    fn seal(&mut self, index: u32, last: bool, plaintext: &[u8]) -> Vec<u8> {
+        let iv = self.chunk_iv(index, last);
+        let mut chunk = plaintext.to_vec();
+        self.apply_keystream(&iv, &mut chunk);
+
+        let tag = self.tag(&iv, &chunk);
+        chunk.extend_from_slice(&tag);
+        chunk
+    }
+    fn open(&mut self, index: u32, last: bool, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        let iv = self.chunk_iv(index, last);
+        let (ciphertext, tag) = chunk.split_at(chunk.len() - E::BLOCK_SIZE);
+
+        if !ct_eq(&self.tag(&iv, ciphertext), tag) {
+            return Err(Error::Authentication);
+        }
+
+        let mut plaintext = ciphertext.to_vec();
+        self.apply_keystream(&iv, &mut plaintext);
+        Ok(plaintext)
+    }
+}
+
+/** Write a container, [`Writer::finish`] must be called to emit the final chunk */
+#[derive(Debug)]
+pub struct Writer<C: BlockCipher, W> {
+    inner: W,
+    sealer: ChunkSealer<C::Encryptor>,
+    chunk_size: usize,
+    index: u32,
+    buf: Vec<u8>,
+}
+
+impl<C: BlockCipher, W: Write> Writer<C, W> {
+    /** Write the header to `inner`
+     *
+     * `key` is the final cipher key, `kdf` only records how it was obtained.
+     * `nonce` must be `block size - 16` bytes long and never reused with the same key.
+     */
+    pub fn new(
+        key: &[u8],
+        kdf: Kdf,
+        nonce: &[u8],
+        chunk_size: u32,
+        mut inner: W,
+    ) -> Result<Self, Error> {
+        let variant = variant_of::<C>();
+        if key.len() != C::BLOCK_SIZE {
+            return Err(Error::InvalidKeyLength(key.len()));
+        }
+        if nonce.len() != Header::nonce_size(variant) {
+            return Err(Error::InvalidHeader(
+                "nonce size does not match the variant",
+            ));
+        }
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(Error::InvalidHeader("chunk size out of range"));
+        }
+        kdf.check()?;
+
+        let cipher = C::Encryptor::new_from_slice(key);
+        let header = Header {
+            variant,
+            mode: Mode::CtrCmac,
+            kdf,
+            chunk_size,
+            key_id: key_id(&cipher),
+            nonce: nonce.to_vec(),
+        };
+        inner.write_all(&header.to_bytes())?;
+
+        Ok(Self {
+            inner,
+            sealer: ChunkSealer::new(&cipher, &header),
+            chunk_size: chunk_size as usize,
+            index: 0,
+            buf: Vec::new(),
+        })
+    }
+    /** Write the final chunk and return the inner writer */
+    pub fn finish(mut self) -> Result<W, Error> {
+        let chunk = self.sealer.seal(self.index, true, &self.buf);
+        self.inner.write_all(&chunk)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<C: BlockCipher, W: Write> Write for Writer<C, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+
+        /* a full chunk is kept back since it may turn out to be the final one */
+        while self.buf.len() > self.chunk_size {
+            let plaintext: Vec<u8> = self.buf.drain(..self.chunk_size).collect();
+            let chunk = self.sealer.seal(self.index, false, &plaintext);
+            self.index = self
+                .index
+                .checked_add(1)
+                .ok_or_else(|| io::Error::other("too many chunks"))?;
+            self.inner.write_all(&chunk)?;
+        }
+
+        Ok(data.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/** Read and verify the chunks following a header
+ *
+ * A chunk is only released after its tag is verified.
+ */
+#[derive(Debug)]
+pub struct Reader<C: BlockCipher, R> {
+    inner: R,
+    header: Header,
+    sealer: ChunkSealer<C::Encryptor>,
+    chunk_size: usize,
+    index: u32,
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<C: BlockCipher, R: Read> Reader<C, R> {
+    /** `inner` must be positioned right after the header, see [`Header::read_from`] */
+    pub fn new(header: Header, key: &[u8], inner: R) -> Result<Self, Error> {
+        if header.variant != variant_of::<C>() {
+            return Err(Error::UnsupportedVariant(header.variant.code()));
+        }
+        if header.mode != Mode::CtrCmac {
+            return Err(Error::UnsupportedMode(mode_code(header.mode)));
+        }
+        if key.len() != C::BLOCK_SIZE {
+            return Err(Error::InvalidKeyLength(key.len()));
+        }
+
+        let cipher = C::Encryptor::new_from_slice(key);
+        if !ct_eq(&key_id(&cipher), &header.key_id) {
+            return Err(Error::WrongKey);
+        }
+
+        Ok(Self {
+            inner,
+            sealer: ChunkSealer::new(&cipher, &header),
+            chunk_size: header.chunk_size as usize,
+            header,
+            index: 0,
+            buf: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            eof: false,
+            done: false,
+        })
+    }
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    fn next_chunk(&mut self) -> Result<(), Error> {
+        let full = self.chunk_size + C::BLOCK_SIZE;
+
+        /* one more byte tells whether this chunk is the final one */
+        while self.buf.len() <= full && !self.eof {
+            let mut tmp = vec![0; full + 1 - self.buf.len()];
+            let n = self.inner.read(&mut tmp)?;
+            self.eof = n == 0;
+            self.buf.extend_from_slice(&tmp[..n]);
+        }
+
+        if self.buf.len() > full {
+            let chunk: Vec<u8> = self.buf.drain(..full).collect();
+            self.out = self.sealer.open(self.index, false, &chunk)?;
+            self.index = self.index.checked_add(1).ok_or(Error::Authentication)?;
+        } else {
+            let chunk = std::mem::take(&mut self.buf);
+            if chunk.len() < C::BLOCK_SIZE {
+                return Err(Error::Truncated);
+            }
+
+            match self.sealer.open(self.index, true, &chunk) {
+                Ok(plaintext) => self.out = plaintext,
+                /* a valid non-final chunk at the end means the rest was cut off */
+                Err(Error::Authentication)
+                    if chunk.len() == full
+                        && self.sealer.open(self.index, false, &chunk).is_ok() =>
+                {
+                    return Err(Error::Truncated);
+                }
+                Err(err) => return Err(err),
+            }
+            self.done = true;
+        }
+        self.out_pos = 0;
+
+        Ok(())
+    }
+}
+
+impl<C: BlockCipher, R: Read> Read for Reader<C, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.out_pos == self.out.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+
+        let len = buf.len().min(self.out.len() - self.out_pos);
+        buf[..len].copy_from_slice(&self.out[self.out_pos..self.out_pos + len]);
+        self.out_pos += len;
+
+        Ok(len)
+    }
+}
@@ -0,0 +1,82 @@
+use std::{fmt, io};
+
+use crate::keys::Weakness;
+
+/** Errors reported by the modes of operation, the container format and the key checks */
+#[derive(Debug)]
+pub enum Error {
+    /** The authentication tag does not match */
+    Authentication,
+    /** The padding of the last block is malformed */
+    Padding,
+    /** The input ends in the middle of a block, before the tag or before the final chunk */
+    Truncated,
+    /** The input does not start with the container magic number */
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnsupportedVariant(u8),
+    UnsupportedMode(u8),
+    UnsupportedKdf(u8),
+    /** A header field holds a value which is never written */
+    InvalidHeader(&'static str),
+    /** The key is not as long as the block of the variant */
+    InvalidKeyLength(usize),
+    /** The key identifier in the header does not belong to the given key */
+    WrongKey,
+    /** The key generates S-Boxes with a known weak pattern, see `keys` */
+    WeakKey(Weakness),
+    /** The power-on self-test failed, see `selftest` */
+    #[cfg(feature = "self-test")]
+    SelfTest(crate::selftest::Failure),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Authentication => write!(f, "authentication failed"),
+            Self::Padding => write!(f, "invalid padding"),
+            Self::Truncated => write!(f, "truncated input"),
+            Self::BadMagic => write!(f, "not a NarrowWay container"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported format version {version}"),
+            Self::UnsupportedVariant(variant) => write!(f, "unsupported variant {variant}"),
+            Self::UnsupportedMode(mode) => write!(f, "unsupported mode {mode}"),
+            Self::UnsupportedKdf(kdf) => write!(f, "unsupported key derivation function {kdf}"),
+            Self::InvalidHeader(reason) => write!(f, "invalid header: {reason}"),
+            Self::InvalidKeyLength(len) => write!(f, "invalid key length {len}"),
+            Self::WrongKey => write!(f, "wrong key"),
+            Self::WeakKey(weakness) => write!(f, "weak key: {weakness}"),
+            #[cfg(feature = "self-test")]
+            Self::SelfTest(failure) => write!(f, "self-test failed: {failure}"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        /* unwrap errors which went through a `Read` or `Write` implementation */
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return *err.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Self::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{Cipher256, Cipher384, Cipher512};
-use std::alloc::{Layout, alloc, dealloc};
+use alloc::alloc::{Layout, alloc, dealloc};
+use core::mem::MaybeUninit;
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn new_cipher_256(key_raw: *const u8) -> *const u8 {
@@ -7,12 +8,17 @@ pub unsafe extern "C" fn new_cipher_256(key_raw: *const u8) -> *const u8 {
     for (i, byte) in key.iter_mut().enumerate() {
         *byte = unsafe { key_raw.add(i).read() };
     }
-    let cipher = Cipher256::new(key);
-
+    #[cfg(feature = "self-test")]
+    if crate::selftest::run().is_err() {
+        return core::ptr::null();
+    }
     let layout = Layout::new::<Cipher256>();
 
     let addr = unsafe { alloc(layout) };
-    unsafe { std::ptr::write(addr as *mut Cipher256, cipher) };
+    if addr.is_null() {
+        return addr;
+    }
+    Cipher256::init_in_place(unsafe { &mut *(addr as *mut MaybeUninit<Cipher256>) }, key);
     addr
 }
 
@@ -55,12 +61,17 @@ pub unsafe extern "C" fn new_cipher_384(key_raw: *const u8) -> *const u8 {
     for (i, byte) in key.iter_mut().enumerate() {
         *byte = unsafe { key_raw.add(i).read() };
     }
-    let cipher = Cipher384::new(key);
-
+    #[cfg(feature = "self-test")]
+    if crate::selftest::run().is_err() {
+        return core::ptr::null();
+    }
     let layout = Layout::new::<Cipher384>();
 
     let addr = unsafe { alloc(layout) };
-    unsafe { std::ptr::write(addr as *mut Cipher384, cipher) };
+    if addr.is_null() {
+        return addr;
+    }
+    Cipher384::init_in_place(unsafe { &mut *(addr as *mut MaybeUninit<Cipher384>) }, key);
     addr
 }
 
@@ -103,12 +114,17 @@ pub unsafe extern "C" fn new_cipher_512(key_raw: *const u8) -> *const u8 {
     for (i, byte) in key.iter_mut().enumerate() {
         *byte = unsafe { key_raw.add(i).read() };
     }
-    let cipher = Cipher512::new(key);
-
+    #[cfg(feature = "self-test")]
+    if crate::selftest::run().is_err() {
+        return core::ptr::null();
+    }
     let layout = Layout::new::<Cipher512>();
 
     let addr = unsafe { alloc(layout) };
-    unsafe { std::ptr::write(addr as *mut Cipher512, cipher) };
+    if addr.is_null() {
+        return addr;
+    }
+    Cipher512::init_in_place(unsafe { &mut *(addr as *mut MaybeUninit<Cipher512>) }, key);
     addr
 }
 
@@ -144,3 +160,34 @@ pub unsafe extern "C" fn nw_decrypt_512(cipher: *const u8, plaintext_raw: *const
         unsafe { buf.add(i).write(*byte) };
     }
 }
+
+/** Run the self-test unless it already ran, 0 if it passed and -1 if it failed */
+#[cfg(feature = "self-test")]
+#[unsafe(no_mangle)]
+pub extern "C" fn nw_self_test() -> i32 {
+    match crate::selftest::run() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/** Repeat the self-test now, 0 if it passed and -1 if it failed */
+#[cfg(feature = "self-test")]
+#[unsafe(no_mangle)]
+pub extern "C" fn nw_check_integrity() -> i32 {
+    match crate::selftest::check_integrity() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/** 0 if the self-test has not run yet, 1 if it passed and -1 if it failed */
+#[cfg(feature = "self-test")]
+#[unsafe(no_mangle)]
+pub extern "C" fn nw_self_test_status() -> i32 {
+    match crate::selftest::status() {
+        crate::selftest::Status::NotRun => 0,
+        crate::selftest::Status::Passed => 1,
+        crate::selftest::Status::Failed(_) => -1,
+    }
+}
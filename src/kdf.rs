@@ -0,0 +1,56 @@
+use alloc::{vec, vec::Vec};
+
+use crate::modes::{BlockCipher, BlockEncrypt};
+
+/** Iteration count used when the caller has no better value */
+pub const DEFAULT_ITERATIONS: u32 = 20_000;
+
+const PBKDF_LABEL: &[u8] = b"NarrowWay.pbkdf";
+
+/** Davies-Meyer compression `h = E_m(h) ^ h` */
+fn compress<C: BlockEncrypt>(state: &mut [u8], message: &[u8]) {
+    let mut block = state.to_vec();
+    C::new_from_slice(message).encrypt_block(&mut block);
+
+    for (s, b) in state.iter_mut().zip(block) {
+        *s ^= b;
+    }
+}
+
+/** Derive a key of the variant `C` from a passphrase
+ *
+ * `u32 salt length || salt || passphrase` is absorbed with Merkle-Damgård
+ * padding through a Davies-Meyer compression keyed by the message, then the
+ * state is compressed `iterations` more times keyed by itself. Every step is
+ * a key setup of the encrypt-only type, which dominates the cost.
+ */
+pub fn pbkdf<C: BlockCipher>(passphrase: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut message = (salt.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(salt);
+    message.extend_from_slice(passphrase);
+    let bit_len = (message.len() as u64) * 8;
+
+    message.push(0x80);
+    while !(message.len() + 8).is_multiple_of(C::BLOCK_SIZE) {
+        message.push(0);
+    }
+    message.extend_from_slice(&bit_len.to_be_bytes());
+
+    let mut state = vec![0; C::BLOCK_SIZE];
+    state[..PBKDF_LABEL.len()].copy_from_slice(PBKDF_LABEL);
+
+    for block in message.chunks(C::BLOCK_SIZE) {
+        compress::<C::Encryptor>(&mut state, block);
+    }
+
+    for i in 0..iterations {
+        let key = state.clone();
+        let len = state.len();
+        for (s, c) in state[len - 4..].iter_mut().zip(i.to_be_bytes()) {
+            *s ^= c;
+        }
+        compress::<C::Encryptor>(&mut state, &key);
+    }
+
+    state
+}
@@ -0,0 +1,59 @@
+/*! Weak patterns in the S-Boxes a master key generates
+ *
+ * The checks only read the digests of the round-key rows, so they run without
+ * the heap and back `Cipher*::try_new` in every build. `analysis::keys`
+ * re-exports them next to the statistics of `digest_key`.
+ */
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+
+/** Known weak pattern in the S-Boxes a master key generates */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weakness {
+    /** Every row of the round goes through the same S-Box */
+    IdenticalRows { round: usize },
+    /** The row goes through the same S-Box in every round */
+    ConstantRow { row: usize },
+}
+
+impl fmt::Display for Weakness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IdenticalRows { round } => {
+                write!(f, "every row of round {round} uses the same S-Box")
+            }
+            Self::ConstantRow { row } => write!(f, "row {row} uses the same S-Box in every round"),
+        }
+    }
+}
+
+impl core::error::Error for Weakness {}
+
+/** Weak patterns in `digests`, identical rows by round first, then constant rows */
+fn find<const ROWS: usize>(digests: &[[u8; ROWS]]) -> impl Iterator<Item = Weakness> + '_ {
+    let identical = digests
+        .iter()
+        .enumerate()
+        .filter(|(_, digests)| digests.iter().all(|&d| d == digests[0]))
+        .map(|(round, _)| Weakness::IdenticalRows { round });
+    let constant = (0..ROWS)
+        .filter(|&row| digests.iter().all(|d| d[row] == digests[0][row]))
+        .map(|row| Weakness::ConstantRow { row });
+
+    identical.chain(constant)
+}
+
+/** First weak pattern in the `digests` of a cipher, as returned by `Cipher*::digests` */
+pub fn check<const ROWS: usize>(digests: &[[u8; ROWS]]) -> Result<(), Weakness> {
+    match find(digests).next() {
+        Some(weakness) => Err(weakness),
+        None => Ok(()),
+    }
+}
+
+/** Weak patterns in the `digests` of a cipher, as returned by `Cipher*::digests` */
+#[cfg(feature = "alloc")]
+pub fn weaknesses<const ROWS: usize>(digests: &[[u8; ROWS]]) -> Vec<Weakness> {
+    find(digests).collect()
+}
@@ -1,17 +1,44 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 include!(concat!(env!("OUT_DIR"), "/constant.rs"));
 
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod container;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "alloc")]
 mod exports;
+#[cfg(feature = "alloc")]
+pub mod kdf;
+pub mod keys;
+pub mod modes;
 mod nw256;
 mod nw384;
 mod nw512;
-
-pub use nw256::Cipher256;
-pub use nw384::Cipher384;
-pub use nw512::Cipher512;
+pub mod prp;
+#[cfg(feature = "reduced-round")]
+pub mod reduced;
+pub mod reference;
+pub mod rng;
+#[cfg(feature = "self-test")]
+pub mod selftest;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod trace;
+
+pub use modes::{BlockCipher, BlockDecrypt, BlockEncrypt, KeyInit, Mode};
+pub use nw256::{Cipher256, Decryptor256, Encryptor256};
+pub use nw384::{Cipher384, Decryptor384, Encryptor384};
+pub use nw512::{Cipher512, Decryptor512, Encryptor512};
 
 const GF28_M: u8 = 0b1110001;
 
-type SBox = [u8; 256];
+pub type SBox = [u8; 256];
 
 #[macro_export]
 macro_rules! reverse_col {
@@ -26,63 +53,23 @@ macro_rules! reverse_col {
     };
 }
 
-/** Addition on GF(2^8) */
 #[cfg(feature = "poc")]
-fn gf_add(f: u8, g: u8) -> u8 {
-    f ^ g
-}
-
-/** Subtration on GF(2^8) */
-#[cfg(feature = "poc")]
-fn gf_sub(f: u8, g: u8) -> u8 {
-    gf_add(f, g)
-}
+use reference::{gf_mul, gf_mul_inv};
 
-/** Multiple on GF(2^8)
- *
- * Note: m is without x^8 nomial
- */
-#[cfg(feature = "poc")]
-fn gf_mul(f: u8, g: u8, m: u8) -> u8 {
-    let mut cache = Vec::new();
-    for i in 0..8 {
-        if (g >> i) & 1 == 1 {
-            let mut j = f;
-            for _ in 0..i {
-                /* the highest bit is 1 */
-                if j >> 7 == 1 {
-                    j <<= 1;
-                    j = gf_sub(j, m);
-                } else {
-                    j <<= 1;
-                }
-            }
-            cache.push(j);
-        }
-    }
-
-    let mut result = 0;
-    for i in cache {
-        result = gf_add(result, i);
-    }
-    result
-}
-
-/** Multiple inverse on GF(2^8) */
-#[cfg(not(feature = "poc"))]
+/** Multiple on GF(2^8) */
+#[cfg(not(any(feature = "poc", feature = "compact")))]
 fn gf_mul(f: u8, g: u8, _m: u8) -> u8 {
     GF28_TABLE[f as usize][g as usize]
 }
 
-/** Multiple inverse on GF(2^8) */
-#[cfg(feature = "poc")]
-fn gf_mul_inv(f: u8, m: u8) -> u8 {
-    for i in 0..256 {
-        if gf_mul(f, i as u8, m) == 1 {
-            return i as u8;
-        }
+/** Multiple on GF(2^8) through the log and antilog tables */
+#[cfg(all(feature = "compact", not(feature = "poc")))]
+fn gf_mul(f: u8, g: u8, _m: u8) -> u8 {
+    if f == 0 || g == 0 {
+        return 0;
     }
-    0
+    let log = GF28_LOG[f as usize] as usize + GF28_LOG[g as usize] as usize;
+    GF28_EXP[log % 255]
 }
 
 /** Multiple inverse on GF(2^8) */
@@ -91,41 +78,11 @@ fn gf_mul_inv(f: u8, _m: u8) -> u8 {
     GF28_INV[f as usize]
 }
 
-#[cfg(feature = "poc")]
-fn bit_transform(b: u8) -> u8 {
-    let mut bit_array = [0; 8];
-
-    for (i, bit) in bit_array.iter_mut().enumerate() {
-        *bit = (b >> i) & 1;
-    }
-
-    let mut result = [0; 8];
-    for i in 0..8 {
-        result[i] = bit_array[i]
-            ^ bit_array[(i + 2) % 8]
-            ^ bit_array[(i + 4) % 8]
-            ^ bit_array[(i + 6) % 8]
-            ^ bit_array[(i + 7) % 8];
-    }
-
-    let mut b = 0;
-    for (i, bit) in result.iter().enumerate() {
-        b |= *bit << i;
-    }
-
-    b
-}
-
 /** Calculate the round constant */
 fn rc(i: usize) -> u8 {
     #[cfg(feature = "poc")]
     {
-        let mut byte = 2;
-        for _ in 0..(i + 2) {
-            byte = gf_mul(byte, 2, GF28_M);
-        }
-
-        byte
+        reference::rc(i)
     }
     #[cfg(not(feature = "poc"))]
     {
@@ -133,28 +90,14 @@ fn rc(i: usize) -> u8 {
     }
 }
 
-/** Generate pre-S-Box */
-#[cfg(feature = "poc")]
-fn s0_gen() -> SBox {
-    let mut s_box = [0; 256];
-    for (i, byte) in s_box.iter_mut().enumerate() {
-        *byte = bit_transform(gf_mul_inv(i as u8, GF28_M));
-    }
-    s_box
-}
-
 /** Generate S-Box */
 #[cfg(feature = "poc")]
 fn s_box_gen(key: u8) -> SBox {
-    let mut s0 = s0_gen();
-    for byte in &mut s0 {
-        *byte ^= key;
-    }
-    s0
+    reference::s_box_gen(key)
 }
 
 fn digest_key(key: &[u8]) -> u8 {
-    use std::cmp::max;
+    use core::cmp::max;
     let mut byte = max(key[0], 1);
 
     for i in key.iter().skip(1) {
@@ -174,7 +117,24 @@ fn s_box_gen(key: u8) -> SBox {
     s0
 }
 
+/** Generate the inverse of `s_box_gen(key)`, `S0 ^ key` is inverted by `S0_INV[y ^ key]` */
+#[cfg(not(feature = "poc"))]
+fn s_box_inv_gen(key: u8) -> SBox {
+    let mut s_inv = [0; 256];
+    for (i, byte) in s_inv.iter_mut().enumerate() {
+        *byte = S0_INV[i ^ key as usize];
+    }
+    s_inv
+}
+
+/** Generate the inverse of `s_box_gen(key)` */
+#[cfg(feature = "poc")]
+fn s_box_inv_gen(key: u8) -> SBox {
+    s_inv_gen(&reference::s_box_gen(key))
+}
+
 /** Generate Inverse S-Box */
+#[cfg(any(feature = "alloc", feature = "poc"))]
 fn s_inv_gen(s_box: &SBox) -> SBox {
     let mut s_inv = [0; 256];
 
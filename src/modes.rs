@@ -0,0 +1,227 @@
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+/** Key setup shared by NarrowWay-256, NarrowWay-384 and NarrowWay-512
+ *
+ * Key size of each variant is equal to its block size.
+ */
+pub trait KeyInit: Sized {
+    const BLOCK_SIZE: usize;
+
+    /** Panics if `key` is not `BLOCK_SIZE` bytes long */
+    fn new_from_slice(key: &[u8]) -> Self;
+}
+
+/** Encryption direction, all CTR and CMAC need */
+pub trait BlockEncrypt: KeyInit {
+    /** Encrypt a block in place, `block` must be `BLOCK_SIZE` bytes long */
+    fn encrypt_block(&self, block: &mut [u8]);
+}
+
+/** Decryption direction */
+pub trait BlockDecrypt: KeyInit {
+    /** Decrypt a block in place, `block` must be `BLOCK_SIZE` bytes long */
+    fn decrypt_block(&self, block: &mut [u8]);
+}
+
+/** Common interface of NarrowWay-256, NarrowWay-384 and NarrowWay-512
+ *
+ * `Encryptor` and `Decryptor` are the same variant keeping only the S-Boxes of one direction.
+ */
+pub trait BlockCipher: BlockEncrypt + BlockDecrypt {
+    type Encryptor: BlockEncrypt;
+    type Decryptor: BlockDecrypt;
+
+    /** Copy the encryption S-Boxes and round keys, without a second key setup */
+    fn encryptor(&self) -> Self::Encryptor;
+}
+
+/** Mode of operation */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /** CBC with PKCS#7 padding */
+    Cbc,
+    /** Counter mode, the counter occupies the last 8 bytes of the IV */
+    Ctr,
+    /** Counter mode followed by CMAC over `IV || ciphertext`
+     *
+     * Both subkeys are derived from the cipher key, the tag is one block long.
+     */
+    CtrCmac,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Cbc, Mode::Ctr, Mode::CtrCmac];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cbc => "cbc",
+            Self::Ctr => "ctr",
+            Self::CtrCmac => "ctr-cmac",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+#[cfg(feature = "std")]
+const CTR_KEY_LABEL: &[u8] = b"NarrowWay.ctr";
+#[cfg(feature = "std")]
+const CMAC_KEY_LABEL: &[u8] = b"NarrowWay.mac";
+
+/** Encrypt a zero-padded label, used to derive independent values from one key */
+#[cfg(feature = "std")]
+pub(crate) fn derive_block<C: BlockEncrypt>(cipher: &C, label: &[u8]) -> Vec<u8> {
+    let mut block = vec![0; C::BLOCK_SIZE];
+    block[..label.len()].copy_from_slice(label);
+    cipher.encrypt_block(&mut block);
+
+    block
+}
+
+#[cfg(feature = "std")]
+fn derive_subkey<C: BlockEncrypt, E: KeyInit>(cipher: &C, label: &[u8]) -> E {
+    E::new_from_slice(&derive_block(cipher, label))
+}
+
+/** Derive the CTR and CMAC ciphers used by [`Mode::CtrCmac`], as `E` which may be encrypt-only */
+#[cfg(feature = "std")]
+pub(crate) fn ctr_cmac_ciphers<C: BlockEncrypt, E: BlockEncrypt>(cipher: &C) -> (E, Cmac<E>) {
+    (
+        derive_subkey(cipher, CTR_KEY_LABEL),
+        Cmac::new(derive_subkey(cipher, CMAC_KEY_LABEL)),
+    )
+}
+
+/** Build the `counter`-th counter block from `iv`
+ *
+ * The counter is added to the last 8 bytes of the IV as a big-endian integer.
+ */
+pub fn ctr_block(iv: &[u8], counter: u64, block: &mut [u8]) {
+    block.copy_from_slice(iv);
+
+    let len = block.len();
+    let low = u64::from_be_bytes(block[len - 8..].try_into().unwrap());
+    block[len - 8..].copy_from_slice(&low.wrapping_add(counter).to_be_bytes());
+}
+
+/** Reduction constant of `x^n` for doubling in GF(2^n), as big-endian bytes */
+#[cfg(feature = "alloc")]
+fn doubling_constant(block_size: usize) -> &'static [u8] {
+    match block_size {
+        32 => &[0x04, 0x25],
+        48 => &[0x10, 0x0d],
+        64 => &[0x01, 0x25],
+        _ => panic!("unsupported block size {block_size}"),
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn double(block: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; block.len()];
+
+    for i in 0..block.len() {
+        result[i] = block[i] << 1;
+        if i + 1 < block.len() {
+            result[i] |= block[i + 1] >> 7;
+        }
+    }
+
+    if block[0] >> 7 == 1 {
+        let constant = doubling_constant(block.len());
+        let offset = block.len() - constant.len();
+        for (i, byte) in constant.iter().enumerate() {
+            result[offset + i] ^= byte;
+        }
+    }
+
+    result
+}
+
+/** Compare two byte strings in constant time */
+#[cfg(feature = "alloc")]
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/** CMAC (OMAC1) over a NarrowWay variant
+ *
+ * Subkeys are doubled in GF(2^n) with the pentanomials
+ * `x^256 + x^10 + x^5 + x^2 + 1`, `x^384 + x^12 + x^3 + x^2 + 1` and
+ * `x^512 + x^8 + x^5 + x^2 + 1`.
+ */
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Cmac<C> {
+    cipher: C,
+    k1: Vec<u8>,
+    k2: Vec<u8>,
+    state: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl<C: BlockEncrypt> Cmac<C> {
+    pub fn new(cipher: C) -> Self {
+        let mut l = vec![0; C::BLOCK_SIZE];
+        cipher.encrypt_block(&mut l);
+        let k1 = double(&l);
+        let k2 = double(&k1);
+
+        Self {
+            cipher,
+            k1,
+            k2,
+            state: vec![0; C::BLOCK_SIZE],
+            buf: Vec::with_capacity(C::BLOCK_SIZE),
+        }
+    }
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            /* the last block is only processed on finalization */
+            if self.buf.len() == C::BLOCK_SIZE {
+                for (s, b) in self.state.iter_mut().zip(&self.buf) {
+                    *s ^= b;
+                }
+                self.cipher.encrypt_block(&mut self.state);
+                self.buf.clear();
+            }
+
+            let len = (C::BLOCK_SIZE - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..len]);
+            data = &data[len..];
+        }
+    }
+    /** Finish the computation and return the tag */
+    pub fn finalize(mut self) -> Vec<u8> {
+        self.finalize_reset()
+    }
+    /** Return the tag and reset the state for a new message under the same key */
+    pub fn finalize_reset(&mut self) -> Vec<u8> {
+        let subkey = if self.buf.len() == C::BLOCK_SIZE {
+            &self.k1
+        } else {
+            self.buf.push(0x80);
+            self.buf.resize(C::BLOCK_SIZE, 0);
+            &self.k2
+        };
+
+        for ((s, b), k) in self.state.iter_mut().zip(&self.buf).zip(subkey) {
+            *s ^= b ^ k;
+        }
+        self.cipher.encrypt_block(&mut self.state);
+
+        let tag = core::mem::replace(&mut self.state, vec![0; C::BLOCK_SIZE]);
+        self.buf.clear();
+        tag
+    }
+    /** Finish the computation and compare the tag in constant time */
+    pub fn verify(self, tag: &[u8]) -> bool {
+        ct_eq(&self.finalize(), tag)
+    }
+}
@@ -1,3 +1,12 @@
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+use core::mem::MaybeUninit;
+
+#[cfg(feature = "std")]
+use crate::error::Error;
+use crate::keys::{self, Weakness};
+use crate::modes::{BlockCipher, BlockDecrypt, BlockEncrypt, KeyInit};
+use crate::trace::{Observer, Step, Traceable};
 use crate::*;
 
 type Key512 = [u8; 64];
@@ -51,6 +60,19 @@ fn round_key_gen_512(key: &Key512, round: usize) -> Key512 {
     round_key
 }
 
+/** Inverse of `round_key_gen_512`, recover the key a round key was derived from */
+fn round_key_inv_512(round_key: &Key512, round: usize) -> Key512 {
+    let mut key = [0; 64];
+
+    key[0] = gf_mul_inv(round_key[0] ^ rc(round), GF28_M).rotate_right(4);
+
+    for i in 1..64 {
+        key[i] = gf_mul_inv(round_key[i] ^ round_key[i - 1], GF28_M).rotate_right(4);
+    }
+
+    key
+}
+
 #[derive(Debug)]
 struct Matrix512([[u8; 8]; 8]);
 
@@ -97,18 +119,164 @@ impl Matrix512 {
     }
 }
 
+fn encrypt_512<O: Observer + ?Sized>(
+    s_boxes: &[[SBox; 8]; ROUND_512],
+    round_keys: &[Key512; ROUND_512],
+    digests: &[[u8; 8]; ROUND_512],
+    block: Block512,
+    observer: &mut O,
+) -> Block512 {
+    let mut mat = Matrix512::new(block);
+    observer.begin(false, &mat.0);
+
+    for round in 0..ROUND_512 {
+        observer.round_key(round, &round_keys[round], &digests[round]);
+        mat.shift_columns();
+        observer.step(round, Step::ShiftColumns, &mat.0);
+        sub_bytes_512(&s_boxes[round], &mut mat);
+        observer.step(round, Step::SubstituteBytes, &mat.0);
+        apply_round_512(&mut mat, &round_keys[round]);
+        observer.step(round, Step::ApplyRoundKey, &mat.0);
+    }
+
+    observer.end(&mat.0);
+    mat.dump()
+}
+
+fn decrypt_512<O: Observer + ?Sized>(
+    s_inves: &[[SBox; 8]; ROUND_512],
+    round_keys: &[Key512; ROUND_512],
+    digests: &[[u8; 8]; ROUND_512],
+    block: Block512,
+    observer: &mut O,
+) -> Block512 {
+    let mut mat = Matrix512::new(block);
+    observer.begin(true, &mat.0);
+
+    for round in (0..ROUND_512).rev() {
+        observer.round_key(round, &round_keys[round], &digests[round]);
+        apply_round_inv_512(&mut mat, &round_keys[round]);
+        observer.step(round, Step::ApplyRoundKey, &mat.0);
+        sub_bytes_inv_512(&s_inves[round], &mut mat);
+        observer.step(round, Step::SubstituteBytes, &mat.0);
+        mat.shift_columns_inv();
+        observer.step(round, Step::ShiftColumns, &mat.0);
+    }
+
+    observer.end(&mat.0);
+    mat.dump()
+}
+
+/** `digest_key` of every row of a round key, shared by the key setup of all three types */
+fn row_digests_512(round_key: &Key512) -> [u8; 8] {
+    let mut digests = [0; 8];
+    for (s, digest) in digests.iter_mut().enumerate() {
+        *digest = digest_key(&round_key[8 * s..8 * s + 8]);
+    }
+    digests
+}
+
 /** NarrowWay-512 block cipher */
 #[derive(Debug)]
 pub struct Cipher512 {
     s_boxes: [[SBox; 8]; ROUND_512],
     s_inves: [[SBox; 8]; ROUND_512],
     round_keys: [Key512; ROUND_512],
+    digests: [[u8; 8]; ROUND_512],
 }
 
 impl Cipher512 {
+    /** Number of rounds */
+    pub const ROUNDS: usize = ROUND_512;
+
     pub fn new(key: Key512) -> Self {
-        let mut s_boxes = [[[0; 256]; 8]; ROUND_512];
-        let mut s_inves = [[[0; 256]; 8]; ROUND_512];
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        Self::build(key)
+    }
+    /** Like `new`, but build the cipher directly on the heap */
+    #[cfg(feature = "alloc")]
+    pub fn new_boxed(key: Key512) -> Box<Self> {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        Self::build_boxed(key)
+    }
+    /** Build the cipher in memory owned by the caller, such as a static or a foreign allocation */
+    pub fn init_in_place(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        Self::init(cipher, key)
+    }
+    /** Replace the key, rebuilding round keys and S-Boxes in the existing storage */
+    pub fn set_key(&mut self, key: Key512) {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        /* every field is plain bytes, so the old value needs no drop and init overwrites all of it */
+        let cipher = unsafe { &mut *(self as *mut Self as *mut MaybeUninit<Self>) };
+        Self::init(cipher, key);
+    }
+    /** `new` without waiting for the self-test */
+    pub(crate) fn build(key: Key512) -> Self {
+        let mut cipher = MaybeUninit::uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** `new_boxed` without waiting for the self-test, which uses it so it runs on small stacks too */
+    #[cfg(feature = "alloc")]
+    pub(crate) fn build_boxed(key: Key512) -> Box<Self> {
+        let mut cipher = Box::new_uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** Write every field of `cipher` one S-Box at a time, so no copy of the whole cipher is made */
+    fn init(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        let ptr = cipher.as_mut_ptr();
+
+        /* fields are only written through raw pointers until all of them are initialized */
+        unsafe {
+            let round_keys = &raw mut (*ptr).round_keys;
+            round_keys.write(Self::expand_key(key));
+            let round_keys = &*round_keys;
+            let s_boxes = &raw mut (*ptr).s_boxes;
+            let s_inves = &raw mut (*ptr).s_inves;
+            let digests = &raw mut (*ptr).digests;
+
+            for (round, round_key) in round_keys.iter().enumerate() {
+                let digest = row_digests_512(round_key);
+                for (s, &digest) in digest.iter().enumerate() {
+                    (&raw mut (*s_boxes)[round][s]).write(s_box_gen(digest));
+                    (&raw mut (*s_inves)[round][s]).write(s_box_inv_gen(digest));
+                }
+                (&raw mut (*digests)[round]).write(digest);
+            }
+
+            cipher.assume_init_mut()
+        }
+    }
+    /** Like `new`, but reject keys whose S-Boxes show a known weak pattern, see `keys`
+     *
+     * Needs neither `std` nor the heap. A failed self-test panics as in `new`.
+     */
+    pub fn try_new(key: Key512) -> Result<Self, Weakness> {
+        let cipher = Self::new(key);
+        keys::check(&cipher.digests)?;
+        Ok(cipher)
+    }
+    /** Like `try_new`, but with the `self-test` feature a failed self-test is returned instead of panicking */
+    #[cfg(feature = "std")]
+    pub fn new_checked(key: Key512) -> Result<Self, Error> {
+        #[cfg(feature = "self-test")]
+        crate::selftest::run().map_err(Error::SelfTest)?;
+        let cipher = Self::build(key);
+        keys::check(&cipher.digests).map_err(Error::WeakKey)?;
+        Ok(cipher)
+    }
+    /** Expand a master key into the round keys of every round */
+    pub fn expand_key(key: Key512) -> [Key512; ROUND_512] {
         let mut round_keys = [[0; 64]; ROUND_512];
 
         for round in 0..ROUND_512 {
@@ -117,40 +285,349 @@ impl Cipher512 {
             } else {
                 round_keys[round] = round_key_gen_512(&round_keys[round - 1], round);
             }
-            for s in 0..8 {
-                s_boxes[round][s] = s_box_gen(digest_key(&round_keys[round][8 * s..8 * s + 8]));
-                s_inves[round][s] = s_inv_gen(&s_boxes[round][s]);
-            }
         }
 
-        Self {
-            s_boxes,
-            s_inves,
-            round_keys,
+        round_keys
+    }
+    /** Recover the master key from the round key of any single round
+     *
+     * Panics if `round` is not below `ROUNDS`.
+     */
+    pub fn recover_key(round_key: Key512, round: usize) -> Key512 {
+        assert!(
+            round < ROUND_512,
+            "NarrowWay-512 has only {ROUND_512} rounds"
+        );
+        let mut key = round_key;
+
+        for round in (0..=round).rev() {
+            key = round_key_inv_512(&key, round);
         }
+
+        key
     }
-    /** Encrypt a block through NarrowWay-512 */
-    pub fn encrypt(&self, block: Block512) -> Block512 {
+    /** Round keys of every round */
+    pub fn round_keys(&self) -> &[Key512; ROUND_512] {
+        &self.round_keys
+    }
+    /** `digest_key` byte of every row of the round keys, which is XORed into `S0` */
+    pub fn digests(&self) -> &[[u8; 8]; ROUND_512] {
+        &self.digests
+    }
+    /** Decrypt a block holding only the last round key
+     *
+     * Earlier round keys are recovered and the inverse S-Boxes generated on the
+     * fly, so nothing but the state and one round key is kept. It is much slower
+     * than `decrypt` on an expanded cipher.
+     */
+    pub fn decrypt_from_last_round_key(last_round_key: Key512, block: Block512) -> Block512 {
         let mut mat = Matrix512::new(block);
+        let mut round_key = last_round_key;
 
-        for round in 0..ROUND_512 {
-            mat.shift_columns();
-            sub_bytes_512(&self.s_boxes[round], &mut mat);
-            apply_round_512(&mut mat, &self.round_keys[round]);
+        for round in (0..ROUND_512).rev() {
+            let mut s_invs = [[0; 256]; 8];
+            for (s, s_inv) in s_invs.iter_mut().enumerate() {
+                *s_inv = s_box_inv_gen(digest_key(&round_key[8 * s..8 * s + 8]));
+            }
+
+            apply_round_inv_512(&mut mat, &round_key);
+            sub_bytes_inv_512(&s_invs, &mut mat);
+            mat.shift_columns_inv();
+
+            round_key = round_key_inv_512(&round_key, round);
         }
 
         mat.dump()
     }
+    /** Encrypt a block through NarrowWay-512 */
+    pub fn encrypt(&self, block: Block512) -> Block512 {
+        self.encrypt_traced(block, &mut ())
+    }
     /** Decrypt a block through NarrowWay-512 */
     pub fn decrypt(&self, block: Block512) -> Block512 {
-        let mut mat = Matrix512::new(block);
+        self.decrypt_traced(block, &mut ())
+    }
+    /** Encrypt a block, reporting every intermediate value to `observer` */
+    pub fn encrypt_traced<O: Observer + ?Sized>(
+        &self,
+        block: Block512,
+        observer: &mut O,
+    ) -> Block512 {
+        encrypt_512(
+            &self.s_boxes,
+            &self.round_keys,
+            &self.digests,
+            block,
+            observer,
+        )
+    }
+    /** Decrypt a block, reporting every intermediate value to `observer` */
+    pub fn decrypt_traced<O: Observer + ?Sized>(
+        &self,
+        block: Block512,
+        observer: &mut O,
+    ) -> Block512 {
+        decrypt_512(
+            &self.s_inves,
+            &self.round_keys,
+            &self.digests,
+            block,
+            observer,
+        )
+    }
+}
 
-        for round in (0..ROUND_512).rev() {
-            apply_round_inv_512(&mut mat, &self.round_keys[round]);
-            sub_bytes_inv_512(&self.s_inves[round], &mut mat);
-            mat.shift_columns_inv();
+impl KeyInit for Cipher512 {
+    const BLOCK_SIZE: usize = 64;
+
+    fn new_from_slice(key: &[u8]) -> Self {
+        Self::new(
+            key.try_into()
+                .expect("key of NarrowWay-512 must be 64 bytes"),
+        )
+    }
+}
+
+impl BlockEncrypt for Cipher512 {
+    fn encrypt_block(&self, block: &mut [u8]) {
+        let result = self.encrypt(block.try_into().unwrap());
+        block.copy_from_slice(&result);
+    }
+}
+
+impl BlockDecrypt for Cipher512 {
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let result = self.decrypt(block.try_into().unwrap());
+        block.copy_from_slice(&result);
+    }
+}
+
+impl BlockCipher for Cipher512 {
+    type Encryptor = Encryptor512;
+    type Decryptor = Decryptor512;
+
+    fn encryptor(&self) -> Encryptor512 {
+        Encryptor512 {
+            s_boxes: self.s_boxes,
+            round_keys: self.round_keys,
+            digests: self.digests,
         }
+    }
+}
 
-        mat.dump()
+/** NarrowWay-512 without inverse S-Boxes, for CTR, CMAC and other modes which never decrypt */
+#[derive(Debug)]
+pub struct Encryptor512 {
+    s_boxes: [[SBox; 8]; ROUND_512],
+    round_keys: [Key512; ROUND_512],
+    digests: [[u8; 8]; ROUND_512],
+}
+
+impl Encryptor512 {
+    pub fn new(key: Key512) -> Self {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        let mut cipher = MaybeUninit::uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** Like `new`, but build it directly on the heap */
+    #[cfg(feature = "alloc")]
+    pub fn new_boxed(key: Key512) -> Box<Self> {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        let mut cipher = Box::new_uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** Build it in memory owned by the caller, such as a static or a foreign allocation */
+    pub fn init_in_place(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        Self::init(cipher, key)
+    }
+    /** Replace the key, rebuilding round keys and S-Boxes in the existing storage */
+    pub fn set_key(&mut self, key: Key512) {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        /* every field is plain bytes, so the old value needs no drop and init overwrites all of it */
+        let cipher = unsafe { &mut *(self as *mut Self as *mut MaybeUninit<Self>) };
+        Self::init(cipher, key);
+    }
+    /** Write every field of `cipher` one S-Box at a time, like `Cipher512::init` */
+    fn init(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        let ptr = cipher.as_mut_ptr();
+
+        /* fields are only written through raw pointers until all of them are initialized */
+        unsafe {
+            let round_keys = &raw mut (*ptr).round_keys;
+            round_keys.write(Cipher512::expand_key(key));
+            let round_keys = &*round_keys;
+            let s_boxes = &raw mut (*ptr).s_boxes;
+            let digests = &raw mut (*ptr).digests;
+
+            for (round, round_key) in round_keys.iter().enumerate() {
+                let digest = row_digests_512(round_key);
+                for (s, &digest) in digest.iter().enumerate() {
+                    (&raw mut (*s_boxes)[round][s]).write(s_box_gen(digest));
+                }
+                (&raw mut (*digests)[round]).write(digest);
+            }
+
+            cipher.assume_init_mut()
+        }
+    }
+    /** Round keys of every round */
+    pub fn round_keys(&self) -> &[Key512; ROUND_512] {
+        &self.round_keys
+    }
+    /** `digest_key` byte of every row of the round keys */
+    pub fn digests(&self) -> &[[u8; 8]; ROUND_512] {
+        &self.digests
+    }
+    /** Encrypt a block through NarrowWay-512 */
+    pub fn encrypt(&self, block: Block512) -> Block512 {
+        encrypt_512(
+            &self.s_boxes,
+            &self.round_keys,
+            &self.digests,
+            block,
+            &mut (),
+        )
+    }
+}
+
+impl KeyInit for Encryptor512 {
+    const BLOCK_SIZE: usize = 64;
+
+    fn new_from_slice(key: &[u8]) -> Self {
+        Self::new(
+            key.try_into()
+                .expect("key of NarrowWay-512 must be 64 bytes"),
+        )
+    }
+}
+
+impl BlockEncrypt for Encryptor512 {
+    fn encrypt_block(&self, block: &mut [u8]) {
+        let result = self.encrypt(block.try_into().unwrap());
+        block.copy_from_slice(&result);
+    }
+}
+
+/** NarrowWay-512 with only the inverse S-Boxes, for decrypting without ever encrypting */
+#[derive(Debug)]
+pub struct Decryptor512 {
+    s_inves: [[SBox; 8]; ROUND_512],
+    round_keys: [Key512; ROUND_512],
+    digests: [[u8; 8]; ROUND_512],
+}
+
+impl Decryptor512 {
+    pub fn new(key: Key512) -> Self {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        let mut cipher = MaybeUninit::uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** Like `new`, but build it directly on the heap */
+    #[cfg(feature = "alloc")]
+    pub fn new_boxed(key: Key512) -> Box<Self> {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        let mut cipher = Box::new_uninit();
+        Self::init(&mut cipher, key);
+
+        /* init wrote every field */
+        unsafe { cipher.assume_init() }
+    }
+    /** Build it in memory owned by the caller, such as a static or a foreign allocation */
+    pub fn init_in_place(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        Self::init(cipher, key)
+    }
+    /** Replace the key, rebuilding round keys and S-Boxes in the existing storage */
+    pub fn set_key(&mut self, key: Key512) {
+        #[cfg(feature = "self-test")]
+        crate::selftest::require();
+        /* every field is plain bytes, so the old value needs no drop and init overwrites all of it */
+        let cipher = unsafe { &mut *(self as *mut Self as *mut MaybeUninit<Self>) };
+        Self::init(cipher, key);
+    }
+    /** Write every field of `cipher` one S-Box at a time, like `Cipher512::init` */
+    fn init(cipher: &mut MaybeUninit<Self>, key: Key512) -> &mut Self {
+        let ptr = cipher.as_mut_ptr();
+
+        /* fields are only written through raw pointers until all of them are initialized */
+        unsafe {
+            let round_keys = &raw mut (*ptr).round_keys;
+            round_keys.write(Cipher512::expand_key(key));
+            let round_keys = &*round_keys;
+            let s_inves = &raw mut (*ptr).s_inves;
+            let digests = &raw mut (*ptr).digests;
+
+            for (round, round_key) in round_keys.iter().enumerate() {
+                let digest = row_digests_512(round_key);
+                for (s, &digest) in digest.iter().enumerate() {
+                    (&raw mut (*s_inves)[round][s]).write(s_box_inv_gen(digest));
+                }
+                (&raw mut (*digests)[round]).write(digest);
+            }
+
+            cipher.assume_init_mut()
+        }
+    }
+    /** Round keys of every round */
+    pub fn round_keys(&self) -> &[Key512; ROUND_512] {
+        &self.round_keys
+    }
+    /** `digest_key` byte of every row of the round keys */
+    pub fn digests(&self) -> &[[u8; 8]; ROUND_512] {
+        &self.digests
+    }
+    /** Decrypt a block through NarrowWay-512 */
+    pub fn decrypt(&self, block: Block512) -> Block512 {
+        decrypt_512(
+            &self.s_inves,
+            &self.round_keys,
+            &self.digests,
+            block,
+            &mut (),
+        )
+    }
+}
+
+impl KeyInit for Decryptor512 {
+    const BLOCK_SIZE: usize = 64;
+
+    fn new_from_slice(key: &[u8]) -> Self {
+        Self::new(
+            key.try_into()
+                .expect("key of NarrowWay-512 must be 64 bytes"),
+        )
+    }
+}
+
+impl BlockDecrypt for Decryptor512 {
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let result = self.decrypt(block.try_into().unwrap());
+        block.copy_from_slice(&result);
+    }
+}
+
+impl Traceable for Cipher512 {
+    const ROUNDS: usize = Cipher512::ROUNDS;
+
+    fn encrypt_block_traced(&self, block: &mut [u8], observer: &mut dyn Observer) {
+        let result = self.encrypt_traced(block.try_into().unwrap(), observer);
+        block.copy_from_slice(&result);
     }
 }
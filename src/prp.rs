@@ -0,0 +1,120 @@
+use crate::Encryptor256;
+
+const FEISTEL_ROUNDS: u8 = 12;
+
+/** Keyed pseudorandom permutation over a small integer domain `0..N`
+ *
+ * It is a balanced Feistel network whose round function is NarrowWay-256,
+ * domains which are not a power of four are handled with cycle-walking.
+ * Typical use is turning sequential IDs into unpredictable public IDs.
+ */
+#[derive(Debug)]
+pub struct Permutation {
+    cipher: Encryptor256,
+    /** Exclusive upper bound, `None` means the whole `2^(2 * half_bits)` range */
+    domain: Option<u64>,
+    half_bits: u32,
+}
+
+impl Permutation {
+    /** Create a permutation over `0..domain`
+     *
+     * Panics if `domain` is zero.
+     */
+    pub fn new(key: [u8; 32], domain: u64) -> Self {
+        assert!(domain > 0, "domain of a permutation must not be empty");
+
+        let bits = 64 - (domain - 1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+
+        Self {
+            cipher: Encryptor256::new(key),
+            domain: if half_bits < 32 && domain == 1 << (2 * half_bits) {
+                None
+            } else {
+                Some(domain)
+            },
+            half_bits,
+        }
+    }
+    /** Create a permutation over `0..2^bits`, `bits` ranges from 1 to 64 */
+    pub fn with_bits(key: [u8; 32], bits: u32) -> Self {
+        assert!((1..=64).contains(&bits), "bits must be in 1..=64");
+
+        let half_bits = bits.div_ceil(2);
+        Self {
+            cipher: Encryptor256::new(key),
+            domain: if bits == 2 * half_bits {
+                None
+            } else {
+                Some(1 << bits)
+            },
+            half_bits,
+        }
+    }
+    /** Map `value` to its pseudorandom image, `value` must be inside the domain */
+    pub fn permute(&self, value: u64) -> u64 {
+        self.check(value);
+
+        let mut x = self.feistel(value);
+        while !self.contains(x) {
+            x = self.feistel(x);
+        }
+        x
+    }
+    /** Map an image produced by [`Permutation::permute`] back to its preimage */
+    pub fn invert(&self, value: u64) -> u64 {
+        self.check(value);
+
+        let mut x = self.feistel_inv(value);
+        while !self.contains(x) {
+            x = self.feistel_inv(x);
+        }
+        x
+    }
+    fn check(&self, value: u64) {
+        assert!(self.contains(value), "value {value} is out of the domain");
+    }
+    fn contains(&self, value: u64) -> bool {
+        match self.domain {
+            Some(domain) => value < domain,
+            None => true,
+        }
+    }
+    fn half_mask(&self) -> u64 {
+        (1 << self.half_bits) - 1
+    }
+    /** Round function of the Feistel network */
+    fn round(&self, round: u8, half: u64) -> u64 {
+        let mut block = [0; 32];
+        block[0] = round;
+        block[1] = self.half_bits as u8;
+        block[2..10].copy_from_slice(&self.domain.unwrap_or(0).to_be_bytes());
+        block[10..18].copy_from_slice(&half.to_be_bytes());
+
+        let out = self.cipher.encrypt(block);
+        u64::from_be_bytes(out[0..8].try_into().unwrap()) & self.half_mask()
+    }
+    fn feistel(&self, value: u64) -> u64 {
+        let mask = self.half_mask();
+        let mut left = (value >> self.half_bits) & mask;
+        let mut right = value & mask;
+
+        for round in 0..FEISTEL_ROUNDS {
+            (left, right) = (right, left ^ self.round(round, right));
+        }
+
+        (left << self.half_bits) | right
+    }
+    fn feistel_inv(&self, value: u64) -> u64 {
+        let mask = self.half_mask();
+        let mut left = (value >> self.half_bits) & mask;
+        let mut right = value & mask;
+
+        for round in (0..FEISTEL_ROUNDS).rev() {
+            (left, right) = (right ^ self.round(round, left), left);
+        }
+
+        (left << self.half_bits) | right
+    }
+}
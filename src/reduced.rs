@@ -0,0 +1,277 @@
+/*!
+ * Reduced-round NarrowWay instances for cryptanalysis
+ *
+ * `ReducedCipher` runs any contiguous span of steps of the round sequence,
+ * from one round up to `MAX_ROUNDS`, and may start or stop in the middle of
+ * a round. With `ReducedCipher::new(key, Cipher256::ROUNDS)` it is exactly
+ * `Cipher256`. These instances are insecure by design and only exist behind
+ * the `reduced-round` feature.
+ */
+
+use alloc::vec::Vec;
+
+use crate::trace::{Observer, Step};
+use crate::*;
+
+/** Largest number of rounds, limited by the round constants */
+pub const MAX_ROUNDS: usize = RC.len();
+
+/** Point of the round sequence right before `step` of `round` */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub round: usize,
+    pub step: Step,
+}
+
+impl Position {
+    pub const fn new(round: usize, step: Step) -> Self {
+        Self { round, step }
+    }
+    /** Start of `round`, which is also the end of the round before it */
+    pub const fn round(round: usize) -> Self {
+        Self::new(round, Step::ShiftColumns)
+    }
+    fn next(self) -> Self {
+        match self.step {
+            Step::ShiftColumns => Self::new(self.round, Step::SubstituteBytes),
+            Step::SubstituteBytes => Self::new(self.round, Step::ApplyRoundKey),
+            Step::ApplyRoundKey => Self::round(self.round + 1),
+        }
+    }
+    fn prev(self) -> Self {
+        match self.step {
+            Step::ShiftColumns => Self::new(self.round - 1, Step::ApplyRoundKey),
+            Step::SubstituteBytes => Self::round(self.round),
+            Step::ApplyRoundKey => Self::new(self.round, Step::SubstituteBytes),
+        }
+    }
+}
+
+pub(crate) type State<const ROWS: usize> = [[u8; 8]; ROWS];
+
+pub(crate) fn round_key_gen<const ROWS: usize>(key: &State<ROWS>, round: usize) -> State<ROWS> {
+    let key = key.as_flattened();
+    let mut round_key = [[0; 8]; ROWS];
+    let flat = round_key.as_flattened_mut();
+
+    flat[0] = gf_mul_inv(key[0].rotate_left(4), GF28_M) ^ rc(round);
+
+    for i in 1..flat.len() {
+        flat[i] = gf_mul_inv(key[i].rotate_left(4), GF28_M) ^ flat[i - 1];
+    }
+
+    round_key
+}
+
+fn shift_down<const ROWS: usize>(state: &mut State<ROWS>, col: usize, step: usize) {
+    reverse_col!(state, col, 0, ROWS - step);
+    reverse_col!(state, col, ROWS - step, ROWS);
+    reverse_col!(state, col, 0, ROWS);
+}
+
+pub(crate) fn shift_columns<const ROWS: usize>(state: &mut State<ROWS>) {
+    for col in 1..8 {
+        let step = col % ROWS;
+
+        if step != 0 {
+            shift_down(state, col, step);
+        }
+    }
+}
+
+pub(crate) fn shift_columns_inv<const ROWS: usize>(state: &mut State<ROWS>) {
+    for col in 1..8 {
+        let step = (ROWS - col % ROWS) % ROWS;
+
+        if step != 0 {
+            shift_down(state, col, step);
+        }
+    }
+}
+
+/** NarrowWay with a configurable span of rounds, see the module documentation */
+#[derive(Debug, Clone)]
+pub struct ReducedCipher<const ROWS: usize> {
+    start: Position,
+    end: Position,
+    round_keys: Vec<State<ROWS>>,
+    digests: Vec<[u8; ROWS]>,
+    s_boxes: Vec<[SBox; ROWS]>,
+    s_inves: Vec<[SBox; ROWS]>,
+}
+
+/** Reduced-round NarrowWay-256 */
+pub type Reduced256 = ReducedCipher<4>;
+/** Reduced-round NarrowWay-384 */
+pub type Reduced384 = ReducedCipher<6>;
+/** Reduced-round NarrowWay-512 */
+pub type Reduced512 = ReducedCipher<8>;
+
+impl<const ROWS: usize> ReducedCipher<ROWS> {
+    pub const BLOCK_SIZE: usize = ROWS * 8;
+
+    /** Cipher running whole rounds `0..rounds` */
+    pub fn new(key: &[u8], rounds: usize) -> Self {
+        assert!(rounds >= 1, "at least one round is required");
+        Self::span(key, Position::round(0), Position::round(rounds))
+    }
+    /** Cipher running every step from `start` up to, but not including, `end`
+     *
+     * Round keys are still derived from round 0, so a span starting at a later
+     * round uses the same keys as the full cipher.
+     */
+    pub fn span(key: &[u8], start: Position, end: Position) -> Self {
+        assert!(
+            matches!(ROWS, 4 | 6 | 8),
+            "NarrowWay has 4, 6 or 8 rows, not {ROWS}"
+        );
+        assert_eq!(
+            key.len(),
+            Self::BLOCK_SIZE,
+            "key must be {} bytes",
+            Self::BLOCK_SIZE
+        );
+        assert!(start <= end, "span starts after it ends");
+        assert!(
+            end <= Position::round(MAX_ROUNDS),
+            "at most {MAX_ROUNDS} rounds are supported"
+        );
+
+        let rounds = match end.step {
+            Step::ShiftColumns => end.round,
+            _ => end.round + 1,
+        };
+        let mut key = load::<ROWS>(key);
+        let mut round_keys = Vec::with_capacity(rounds);
+        let mut digests = Vec::with_capacity(rounds);
+        let mut s_boxes = Vec::with_capacity(rounds);
+        let mut s_inves = Vec::with_capacity(rounds);
+
+        for round in 0..rounds {
+            key = round_key_gen(&key, round);
+            let digest: [u8; ROWS] = core::array::from_fn(|row| digest_key(&key[row]));
+            let s_box: [SBox; ROWS] = core::array::from_fn(|row| s_box_gen(digest[row]));
+
+            round_keys.push(key);
+            digests.push(digest);
+            s_inves.push(core::array::from_fn(|row| s_inv_gen(&s_box[row])));
+            s_boxes.push(s_box);
+        }
+
+        Self {
+            start,
+            end,
+            round_keys,
+            digests,
+            s_boxes,
+            s_inves,
+        }
+    }
+    pub fn start(&self) -> Position {
+        self.start
+    }
+    pub fn end(&self) -> Position {
+        self.end
+    }
+    /** Round keys of every round touched by the span, starting from round 0 */
+    pub fn round_keys(&self) -> &[State<ROWS>] {
+        &self.round_keys
+    }
+    fn step(&self, position: Position, state: &mut State<ROWS>) {
+        let round = position.round;
+
+        match position.step {
+            Step::ShiftColumns => shift_columns(state),
+            Step::SubstituteBytes => {
+                for (row, s_box) in state.iter_mut().zip(&self.s_boxes[round]) {
+                    row.iter_mut()
+                        .for_each(|byte| *byte = s_box[*byte as usize]);
+                }
+            }
+            Step::ApplyRoundKey => {
+                for (row, key) in state.iter_mut().zip(&self.round_keys[round]) {
+                    func_f(row, *key);
+                }
+            }
+        }
+    }
+    fn step_inv(&self, position: Position, state: &mut State<ROWS>) {
+        let round = position.round;
+
+        match position.step {
+            Step::ShiftColumns => shift_columns_inv(state),
+            Step::SubstituteBytes => {
+                for (row, s_inv) in state.iter_mut().zip(&self.s_inves[round]) {
+                    row.iter_mut()
+                        .for_each(|byte| *byte = s_inv[*byte as usize]);
+                }
+            }
+            Step::ApplyRoundKey => {
+                for (row, key) in state.iter_mut().zip(&self.round_keys[round]) {
+                    func_f_inv(row, *key);
+                }
+            }
+        }
+    }
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        self.encrypt_traced(block, &mut ());
+    }
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        self.decrypt_traced(block, &mut ());
+    }
+    /** Encrypt a block in place, reporting every intermediate value to `observer` */
+    pub fn encrypt_traced<O: Observer + ?Sized>(&self, block: &mut [u8], observer: &mut O) {
+        let mut state = load::<ROWS>(block);
+        let mut position = self.start;
+        observer.begin(false, &state);
+
+        while position < self.end {
+            if position.step == Step::ShiftColumns || position == self.start {
+                let round = position.round;
+                observer.round_key(
+                    round,
+                    self.round_keys[round].as_flattened(),
+                    &self.digests[round],
+                );
+            }
+            self.step(position, &mut state);
+            observer.step(position.round, position.step, &state);
+            position = position.next();
+        }
+
+        observer.end(&state);
+        block.copy_from_slice(state.as_flattened());
+    }
+    /** Decrypt a block in place, reporting every intermediate value to `observer` */
+    pub fn decrypt_traced<O: Observer + ?Sized>(&self, block: &mut [u8], observer: &mut O) {
+        let mut state = load::<ROWS>(block);
+        let mut position = self.end;
+        observer.begin(true, &state);
+
+        while position > self.start {
+            let last = position == self.end;
+            position = position.prev();
+
+            if position.step == Step::ApplyRoundKey || last {
+                let round = position.round;
+                observer.round_key(
+                    round,
+                    self.round_keys[round].as_flattened(),
+                    &self.digests[round],
+                );
+            }
+            self.step_inv(position, &mut state);
+            observer.step(position.round, position.step, &state);
+        }
+
+        observer.end(&state);
+        block.copy_from_slice(state.as_flattened());
+    }
+}
+
+fn load<const ROWS: usize>(bytes: &[u8]) -> State<ROWS> {
+    assert_eq!(bytes.len(), ROWS * 8, "block must be {} bytes", ROWS * 8);
+    let mut state = [[0; 8]; ROWS];
+    state.as_flattened_mut().copy_from_slice(bytes);
+    state
+}
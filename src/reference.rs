@@ -0,0 +1,259 @@
+/*!
+ * Reference GF(2^8) arithmetic and a cipher built only on it
+ *
+ * These are the functions `build.rs` computes the constant tables with, and
+ * what the `poc` feature runs instead of the tables. They are compiled into
+ * every build so both paths can be compared against each other in one test
+ * run. Everything here is slow and meant for checking, not for encryption.
+ */
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::{GF28_M, SBox};
+#[cfg(feature = "alloc")]
+use crate::{func_f, func_f_inv, s_inv_gen};
+
+/** Addition on GF(2^8) */
+pub fn gf_add(f: u8, g: u8) -> u8 {
+    f ^ g
+}
+
+/** Subtration on GF(2^8) */
+pub fn gf_sub(f: u8, g: u8) -> u8 {
+    gf_add(f, g)
+}
+
+/** Multiple on GF(2^8)
+ *
+ * Note: m is without x^8 nomial
+ */
+pub fn gf_mul(f: u8, g: u8, m: u8) -> u8 {
+    let mut cache = [0; 8];
+    for (i, term) in cache.iter_mut().enumerate() {
+        if (g >> i) & 1 == 1 {
+            let mut j = f;
+            for _ in 0..i {
+                /* the highest bit is 1 */
+                if j >> 7 == 1 {
+                    j <<= 1;
+                    j = gf_sub(j, m);
+                } else {
+                    j <<= 1;
+                }
+            }
+            *term = j;
+        }
+    }
+
+    let mut result = 0;
+    for i in cache {
+        result = gf_add(result, i);
+    }
+    result
+}
+
+/** Multiple inverse on GF(2^8)
+ *
+ * `f^254` by square-and-multiply, since `f^255 = 1` for every nonzero `f`,
+ * and 0 for 0 as in `GF28_INV`.
+ */
+pub fn gf_mul_inv(f: u8, m: u8) -> u8 {
+    let mut result = 1;
+    let mut square = f;
+    let mut exponent = 254u8;
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, square, m);
+        }
+        square = gf_mul(square, square, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+/** Affine bit mixing applied after the inverse in `S0` */
+pub fn bit_transform(b: u8) -> u8 {
+    let mut bit_array = [0; 8];
+
+    for (i, bit) in bit_array.iter_mut().enumerate() {
+        *bit = (b >> i) & 1;
+    }
+
+    let mut result = [0; 8];
+    for i in 0..8 {
+        result[i] = bit_array[i]
+            ^ bit_array[(i + 2) % 8]
+            ^ bit_array[(i + 4) % 8]
+            ^ bit_array[(i + 6) % 8]
+            ^ bit_array[(i + 7) % 8];
+    }
+
+    let mut b = 0;
+    for (i, bit) in result.iter().enumerate() {
+        b |= *bit << i;
+    }
+
+    b
+}
+
+/** Calculate the round constant */
+pub fn rc(i: usize) -> u8 {
+    let mut byte = 2;
+    for _ in 0..(i + 2) {
+        byte = gf_mul(byte, 2, GF28_M);
+    }
+
+    byte
+}
+
+/** Generate pre-S-Box */
+pub fn s0_gen() -> SBox {
+    let mut s_box = [0; 256];
+    for (i, byte) in s_box.iter_mut().enumerate() {
+        *byte = bit_transform(gf_mul_inv(i as u8, GF28_M));
+    }
+    s_box
+}
+
+/** Generate S-Box */
+pub fn s_box_gen(key: u8) -> SBox {
+    let mut s0 = s0_gen();
+    for byte in &mut s0 {
+        *byte ^= key;
+    }
+    s0
+}
+
+/** S-Box digest of a round-key row */
+pub fn digest_key(key: &[u8]) -> u8 {
+    key.iter()
+        .skip(1)
+        .fold(key[0].max(1), |byte, &i| gf_mul(byte, i.max(1), GF28_M))
+}
+
+/** Round key of `round` derived from the previous round key, or the master key for round 0 */
+#[cfg(feature = "alloc")]
+pub fn round_key_gen(key: &[u8], round: usize) -> Vec<u8> {
+    let mut round_key = Vec::with_capacity(key.len());
+    let mut previous = rc(round);
+
+    for &byte in key {
+        previous ^= gf_mul_inv(byte.rotate_left(4), GF28_M);
+        round_key.push(previous);
+    }
+
+    round_key
+}
+
+/** Any variant of NarrowWay, selected by key length, computed with the functions above
+ *
+ * Only the GF(2^8) arithmetic differs from `Cipher*`, Function F and the
+ * layout of the state are shared.
+ */
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct Cipher {
+    round_keys: Vec<Vec<u8>>,
+    s_boxes: Vec<Vec<SBox>>,
+    s_inves: Vec<Vec<SBox>>,
+}
+
+#[cfg(feature = "alloc")]
+impl Cipher {
+    /** Panics unless `key` is 32, 48 or 64 bytes long */
+    pub fn new(key: &[u8]) -> Self {
+        let rounds = match key.len() {
+            32 => crate::Cipher256::ROUNDS,
+            48 => crate::Cipher384::ROUNDS,
+            64 => crate::Cipher512::ROUNDS,
+            len => panic!("no NarrowWay variant has {len}-byte keys"),
+        };
+        let mut round_keys = Vec::with_capacity(rounds);
+        let mut key = key.to_vec();
+        for round in 0..rounds {
+            key = round_key_gen(&key, round);
+            round_keys.push(key.clone());
+        }
+
+        /* s_box_gen per row would rebuild S0 every time */
+        let s0 = s0_gen();
+        let s_boxes: Vec<Vec<SBox>> = round_keys
+            .iter()
+            .map(|key| {
+                key.chunks(8)
+                    .map(|row| {
+                        let digest = digest_key(row);
+                        s0.map(|byte| byte ^ digest)
+                    })
+                    .collect()
+            })
+            .collect();
+        let s_inves = s_boxes
+            .iter()
+            .map(|s_boxes| s_boxes.iter().map(s_inv_gen).collect())
+            .collect();
+
+        Self {
+            round_keys,
+            s_boxes,
+            s_inves,
+        }
+    }
+    fn rows(&self) -> usize {
+        self.round_keys[0].len() / 8
+    }
+    /** Rotate column `col` down by `col` rows for ShiftColumns, or up for its inverse */
+    fn shift_columns(&self, state: &mut [[u8; 8]], inverse: bool) {
+        let rows = self.rows();
+        for col in 1..8 {
+            let column: Vec<u8> = state.iter().map(|row| row[col]).collect();
+            for (row, &byte) in column.iter().enumerate() {
+                let to = match inverse {
+                    false => (row + col) % rows,
+                    true => (row + rows - col % rows) % rows,
+                };
+                state[to][col] = byte;
+            }
+        }
+    }
+    /** Panics if `block` is not as long as the key */
+    pub fn encrypt(&self, block: &[u8]) -> Vec<u8> {
+        let mut state = state_of(block, self.rows());
+
+        for (round_key, s_boxes) in self.round_keys.iter().zip(&self.s_boxes) {
+            self.shift_columns(&mut state, false);
+            for ((row, s_box), key) in state.iter_mut().zip(s_boxes).zip(round_key.chunks(8)) {
+                for byte in row.iter_mut() {
+                    *byte = s_box[*byte as usize];
+                }
+                func_f(row, key.try_into().unwrap());
+            }
+        }
+
+        state.concat()
+    }
+    /** Panics if `block` is not as long as the key */
+    pub fn decrypt(&self, block: &[u8]) -> Vec<u8> {
+        let mut state = state_of(block, self.rows());
+
+        for (round_key, s_inves) in self.round_keys.iter().zip(&self.s_inves).rev() {
+            for ((row, s_inv), key) in state.iter_mut().zip(s_inves).zip(round_key.chunks(8)) {
+                func_f_inv(row, key.try_into().unwrap());
+                for byte in row.iter_mut() {
+                    *byte = s_inv[*byte as usize];
+                }
+            }
+            self.shift_columns(&mut state, true);
+        }
+
+        state.concat()
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn state_of(block: &[u8], rows: usize) -> Vec<[u8; 8]> {
+    assert_eq!(block.len(), rows * 8, "block must be as long as the key");
+    block.chunks(8).map(|row| row.try_into().unwrap()).collect()
+}
@@ -0,0 +1,275 @@
+/*!
+ * Power-on self-test, enabled by the `self-test` feature
+ *
+ * The first `Cipher*::new` runs a known-answer test of encryption and
+ * decryption for every variant and compares the CRC-32 of the constant
+ * tables with values embedded here. Once a test fails, every later
+ * `Cipher*::new` panics, `Cipher*::new_checked` returns
+ * `Error::SelfTest` and the C API hands out null pointers, until the
+ * process restarts. `check_integrity` repeats the tests at any time.
+ */
+
+use std::fmt;
+use std::sync::Mutex;
+
+#[cfg(not(feature = "compact"))]
+use crate::GF28_TABLE;
+use crate::{Cipher256, Cipher384, Cipher512, GF28_INV, RC, S0, S0_INV};
+#[cfg(feature = "compact")]
+use crate::{GF28_EXP, GF28_LOG};
+
+/** Part of the self-test which did not give the expected result */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /** The checksum of a constant table differs from the embedded one */
+    Table(&'static str),
+    /** A known-answer test of a variant, given in bits */
+    Encrypt(usize),
+    Decrypt(usize),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Table(name) => write!(f, "checksum of {name} does not match"),
+            Self::Encrypt(bits) => {
+                write!(f, "NarrowWay-{bits} encryption known-answer test failed")
+            }
+            Self::Decrypt(bits) => {
+                write!(f, "NarrowWay-{bits} decryption known-answer test failed")
+            }
+        }
+    }
+}
+
+/** State of the self-test of this process */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    NotRun,
+    Passed,
+    Failed(Failure),
+}
+
+static STATUS: Mutex<Status> = Mutex::new(Status::NotRun);
+
+/** CRC-32 of the constant tables, computed when they were last regenerated */
+const CHECKSUMS: [(&str, u32); 4] = [
+    ("S0", 0x2daaf4dd),
+    ("S0_INV", 0x24e4a3ab),
+    ("GF28_INV", 0xe6777aeb),
+    ("RC", 0x7a82badf),
+];
+/** CRC-32 of the multiplication tables this build uses */
+#[cfg(not(feature = "compact"))]
+const MUL_CHECKSUMS: [(&str, u32); 1] = [("GF28_TABLE", 0x0fd432ab)];
+#[cfg(feature = "compact")]
+const MUL_CHECKSUMS: [(&str, u32); 2] = [("GF28_LOG", 0xe4a0a69a), ("GF28_EXP", 0x8e7a5ff3)];
+
+/** Key, plaintext and ciphertext of COUNT = 0 in `tests/data/kat/NW*Random.rsp` */
+const KAT_256: [&str; 3] = [
+    "afcd1d7b39a820e2f465b9a16a9e786e4f450980185dc406ec814c72a8b88bf8",
+    "9b74a8516a89391beaa27e740c9fcb53e132451fbe9a822c3cab16c93a1384c5",
+    "76909b3069087ca1ebc2997d0d93814aeff1214422f922e95561db4a7a535bd2",
+];
+const KAT_384: [&str; 3] = [
+    "afcd1d7b39a820e2f465b9a16a9e786e4f450980185dc406ec814c72a8b88bf89b74a8516a89391beaa27e740c9fcb53",
+    "e132451fbe9a822c3cab16c93a1384c5c38ac9419078e53ea6b08c368c48b8f3093db13cddec7e65f6de5b05e026d3c2",
+    "9f5f6748c63e0345c4da5d4a58dbb6554cfc04d6f90f89ca2cec00edbf2a6947a6dcb5a3d8b3c7fe34b1ba75b54a922b",
+];
+const KAT_512: [&str; 3] = [
+    "afcd1d7b39a820e2f465b9a16a9e786e4f450980185dc406ec814c72a8b88bf89b74a8516a89391beaa27e740c9fcb53e132451fbe9a822c3cab16c93a1384c5",
+    "c38ac9419078e53ea6b08c368c48b8f3093db13cddec7e65f6de5b05e026d3c27bdbbbe03fa021862fa93a9855751f8e194dcc00160f4eb5ab801d97973fbb84",
+    "09f5b033419eb11175e31ada771cfc6ff8cee54aa4a99897dad23ea7f826964085fe9daba27f0704616f2e8b4f5335d5ed4b50931f95ed1c046b8562d1aee173",
+];
+
+/** CRC-32 with the IEEE 802.3 polynomial, as used by zlib */
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+fn decode<const N: usize>(hex: &str) -> [u8; N] {
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+    }
+    bytes
+}
+
+/** The tables of this build, in the order of `CHECKSUMS` and `MUL_CHECKSUMS` */
+fn tables() -> Vec<&'static [u8]> {
+    let mut tables: Vec<&[u8]> = vec![&S0, &S0_INV, &GF28_INV, &RC];
+    #[cfg(not(feature = "compact"))]
+    tables.push(GF28_TABLE.as_flattened());
+    #[cfg(feature = "compact")]
+    tables.extend([&GF28_LOG[..], &GF28_EXP[..]]);
+    tables
+}
+
+fn check_tables(tables: &[&[u8]]) -> Result<(), Failure> {
+    let checksums = CHECKSUMS.into_iter().chain(MUL_CHECKSUMS);
+    for ((name, checksum), table) in checksums.zip(tables) {
+        if crc32(table) != checksum {
+            return Err(Failure::Table(name));
+        }
+    }
+    Ok(())
+}
+
+macro_rules! known_answer {
+    ($cipher: ident, $kat: expr, $bits: expr) => {{
+        let cipher = $cipher::build_boxed(decode($kat[0]));
+        let plaintext = decode($kat[1]);
+        let ciphertext = decode($kat[2]);
+
+        if cipher.encrypt(plaintext) != ciphertext {
+            return Err(Failure::Encrypt($bits));
+        }
+        if cipher.decrypt(ciphertext) != plaintext {
+            return Err(Failure::Decrypt($bits));
+        }
+    }};
+}
+
+/** Key, plaintext and ciphertext of every variant, from 256 to 512 bits */
+fn known_answers(kats: [[&str; 3]; 3]) -> Result<(), Failure> {
+    known_answer!(Cipher256, kats[0], 256);
+    known_answer!(Cipher384, kats[1], 384);
+    known_answer!(Cipher512, kats[2], 512);
+    Ok(())
+}
+
+/** Run every test once, without looking at or changing the status */
+fn self_test() -> Result<(), Failure> {
+    check_tables(&tables())?;
+    known_answers([KAT_256, KAT_384, KAT_512])
+}
+
+/** Status of this process, without running anything */
+pub fn status() -> Status {
+    *STATUS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/** Run `test` and record the outcome, unless an earlier run failed */
+fn update(status: &mut Status, test: impl FnOnce() -> Result<(), Failure>) -> Result<(), Failure> {
+    if let Status::Failed(failure) = *status {
+        return Err(failure);
+    }
+    let result = test();
+    *status = match result {
+        Ok(()) => Status::Passed,
+        Err(failure) => Status::Failed(failure),
+    };
+    result
+}
+
+/** Run the self-test unless it already ran, and return its outcome */
+pub fn run() -> Result<(), Failure> {
+    let mut status = STATUS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    match *status {
+        Status::NotRun => update(&mut status, self_test),
+        Status::Passed => Ok(()),
+        Status::Failed(failure) => Err(failure),
+    }
+}
+
+/** Repeat the whole self-test now, a failure disables the crate like one at power-on */
+pub fn check_integrity() -> Result<(), Failure> {
+    update(
+        &mut STATUS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        self_test,
+    )
+}
+
+/** Called by `Cipher*::new`, panics if the self-test failed */
+pub(crate) fn require() {
+    if let Err(failure) = run() {
+        panic!("NarrowWay self-test failed: {failure}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::exports;
+
+    /** Record the outcome of `test` as if the self-test had produced it */
+    fn inject(test: impl FnOnce() -> Result<(), Failure>) -> Result<(), Failure> {
+        update(
+            &mut STATUS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            test,
+        )
+    }
+
+    /* a single test, since a failure disables the crate for the whole process */
+    #[test]
+    fn failure_disables_the_crate() {
+        let mut s0 = S0;
+        s0[0x5a] ^= 1;
+        let mut corrupted = tables();
+        corrupted[0] = &s0;
+        assert_eq!(check_tables(&corrupted), Err(Failure::Table("S0")));
+        /* every inverse S-Box is read from S0_INV, which the KATs only sample */
+        let mut s0_inv = S0_INV;
+        s0_inv[0xa5] ^= 1;
+        let mut corrupted = tables();
+        corrupted[1] = &s0_inv;
+        assert_eq!(check_tables(&corrupted), Err(Failure::Table("S0_INV")));
+        let mut corrupted = tables();
+        let rc = [0; 64];
+        corrupted[3] = &rc;
+        assert_eq!(check_tables(&corrupted), Err(Failure::Table("RC")));
+
+        let wrong = [KAT_512[0], KAT_512[1], KAT_512[1]];
+        assert_eq!(
+            known_answers([KAT_256, KAT_384, wrong]),
+            Err(Failure::Encrypt(512))
+        );
+
+        assert_eq!(run(), Ok(()));
+        assert_eq!(
+            inject(|| check_tables(&corrupted)),
+            Err(Failure::Table("RC"))
+        );
+        assert_eq!(status(), Status::Failed(Failure::Table("RC")));
+        /* later runs keep the first failure, even if they would pass */
+        assert_eq!(inject(|| Ok(())), Err(Failure::Table("RC")));
+        assert_eq!(run(), Err(Failure::Table("RC")));
+        assert_eq!(check_integrity(), Err(Failure::Table("RC")));
+
+        assert!(matches!(
+            Cipher256::new_checked([7; 32]),
+            Err(Error::SelfTest(Failure::Table("RC")))
+        ));
+        assert!(std::panic::catch_unwind(|| Cipher384::new([7; 48])).is_err());
+        assert!(std::panic::catch_unwind(|| Cipher512::new_boxed([7; 64])).is_err());
+
+        let key = [7; 64];
+        unsafe {
+            assert!(exports::new_cipher_256(key.as_ptr()).is_null());
+            assert!(exports::new_cipher_384(key.as_ptr()).is_null());
+            assert!(exports::new_cipher_512(key.as_ptr()).is_null());
+        }
+        assert_eq!(exports::nw_self_test(), -1);
+        assert_eq!(exports::nw_check_integrity(), -1);
+        assert_eq!(exports::nw_self_test_status(), -1);
+    }
+}
@@ -0,0 +1,353 @@
+use crate::error::Error;
+use crate::modes::{
+    BlockCipher, BlockDecrypt, BlockEncrypt, Cmac, Mode, ctr_block, ctr_cmac_ciphers,
+};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/** Cipher the blocks go through, only CBC decryption needs the full one */
+#[derive(Debug)]
+enum Blocks<C, E> {
+    /** The caller's cipher in CBC mode */
+    Full(Box<C>),
+    /** The encryption half of the caller's cipher in CTR mode, or the CTR subkey of CTR-CMAC mode */
+    EncryptOnly(Box<E>),
+}
+
+impl<C: BlockEncrypt + BlockDecrypt, E: BlockEncrypt> Blocks<C, E> {
+    fn encrypt_block(&self, block: &mut [u8]) {
+        match self {
+            Self::Full(cipher) => cipher.encrypt_block(block),
+            Self::EncryptOnly(cipher) => cipher.encrypt_block(block),
+        }
+    }
+    fn decrypt_block(&self, block: &mut [u8]) {
+        match self {
+            Self::Full(cipher) => cipher.decrypt_block(block),
+            Self::EncryptOnly(_) => {
+                unreachable!("only CBC decrypts blocks, and it keeps the full cipher")
+            }
+        }
+    }
+}
+
+/** Block-level state shared by the writer and the reader */
+#[derive(Debug)]
+struct ModeState<C, E> {
+    mode: Mode,
+    cipher: Blocks<C, E>,
+    mac: Option<Cmac<E>>,
+    iv: Vec<u8>,
+    /** Previous ciphertext block in CBC mode */
+    chain: Vec<u8>,
+    counter: u64,
+}
+
+impl<C: BlockCipher> ModeState<C, C::Encryptor> {
+    fn new(cipher: C, mode: Mode, iv: &[u8]) -> Self {
+        assert_eq!(iv.len(), C::BLOCK_SIZE, "IV must be one block long");
+
+        let (cipher, mac) = match mode {
+            Mode::Cbc => (Blocks::Full(Box::new(cipher)), None),
+            Mode::Ctr => (Blocks::EncryptOnly(Box::new(cipher.encryptor())), None),
+            Mode::CtrCmac => {
+                let (ctr, mut mac) = ctr_cmac_ciphers(&cipher);
+                mac.update(iv);
+                (Blocks::EncryptOnly(Box::new(ctr)), Some(mac))
+            }
+        };
+
+        Self {
+            mode,
+            cipher,
+            mac,
+            iv: iv.to_vec(),
+            chain: iv.to_vec(),
+            counter: 0,
+        }
+    }
+    /** XOR the keystream into a (possibly partial) block */
+    fn apply_keystream(&mut self, block: &mut [u8]) {
+        let mut keystream = vec![0; C::BLOCK_SIZE];
+        ctr_block(&self.iv, self.counter, &mut keystream);
+        self.cipher.encrypt_block(&mut keystream);
+        self.counter = self.counter.wrapping_add(1);
+
+        for (byte, key) in block.iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+    }
+    fn encrypt(&mut self, data: &mut [u8]) {
+        for block in data.chunks_mut(C::BLOCK_SIZE) {
+            match self.mode {
+                Mode::Cbc => {
+                    for (byte, prev) in block.iter_mut().zip(&self.chain) {
+                        *byte ^= prev;
+                    }
+                    self.cipher.encrypt_block(block);
+                    self.chain.copy_from_slice(block);
+                }
+                Mode::Ctr | Mode::CtrCmac => self.apply_keystream(block),
+            }
+        }
+
+        if let Some(mac) = &mut self.mac {
+            mac.update(data);
+        }
+    }
+    fn decrypt(&mut self, data: &mut [u8]) {
+        if let Some(mac) = &mut self.mac {
+            mac.update(data);
+        }
+
+        for block in data.chunks_mut(C::BLOCK_SIZE) {
+            match self.mode {
+                Mode::Cbc => {
+                    let cipher_block = block.to_vec();
+                    self.cipher.decrypt_block(block);
+                    for (byte, prev) in block.iter_mut().zip(&self.chain) {
+                        *byte ^= prev;
+                    }
+                    self.chain = cipher_block;
+                }
+                Mode::Ctr | Mode::CtrCmac => self.apply_keystream(block),
+            }
+        }
+    }
+}
+
+/** Encrypt everything written to it and forward the ciphertext to `W`
+ *
+ * Partial blocks are buffered, [`EncryptWriter::finish`] must be called to
+ * emit the last block together with the padding or the tag.
+ */
+#[derive(Debug)]
+pub struct EncryptWriter<C: BlockCipher, W> {
+    inner: W,
+    state: ModeState<C, C::Encryptor>,
+    buf: Vec<u8>,
+}
+
+impl<C: BlockCipher, W: Write> EncryptWriter<C, W> {
+    /** Panics if `iv` is not one block long */
+    pub fn new(cipher: C, mode: Mode, iv: &[u8], inner: W) -> Self {
+        Self {
+            inner,
+            state: ModeState::new(cipher, mode, iv),
+            buf: Vec::with_capacity(C::BLOCK_SIZE),
+        }
+    }
+    /** Flush the buffered data, write the padding or the tag and return the inner writer */
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut last = std::mem::take(&mut self.buf);
+
+        if self.state.mode == Mode::Cbc {
+            let pad = C::BLOCK_SIZE - last.len();
+            last.resize(C::BLOCK_SIZE, pad as u8);
+        }
+        self.state.encrypt(&mut last);
+        self.inner.write_all(&last)?;
+
+        if let Some(mac) = self.state.mac.take() {
+            self.inner.write_all(&mac.finalize())?;
+        }
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<C: BlockCipher, W: Write> Write for EncryptWriter<C, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+
+        let len = self.buf.len() / C::BLOCK_SIZE * C::BLOCK_SIZE;
+        if len > 0 {
+            let mut blocks: Vec<u8> = self.buf.drain(..len).collect();
+            self.state.encrypt(&mut blocks);
+            self.inner.write_all(&blocks)?;
+        }
+
+        Ok(data.len())
+    }
+    /** Flush the inner writer, buffered partial blocks are kept until [`EncryptWriter::finish`] */
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/** Decrypt the ciphertext read from `R`
+ *
+ * In [`Mode::CtrCmac`] plaintext is released before the tag is checked, a
+ * mismatching tag is reported as an [`io::ErrorKind::InvalidData`] error at
+ * the end of the stream, so the output must not be trusted until then.
+ */
+#[derive(Debug)]
+pub struct DecryptReader<C: BlockCipher, R> {
+    inner: R,
+    state: ModeState<C, C::Encryptor>,
+    /** Ciphertext read from `inner` but not decrypted yet */
+    pending: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    /** Offset of the next byte read from `inner`, relative to the start of ciphertext */
+    offset: u64,
+    /** Offset of the next plaintext byte returned */
+    position: u64,
+    /** Plaintext bytes to drop after seeking into the middle of a block */
+    skip: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<C: BlockCipher, R: Read> DecryptReader<C, R> {
+    /** Panics if `iv` is not one block long */
+    pub fn new(cipher: C, mode: Mode, iv: &[u8], inner: R) -> Self {
+        Self {
+            inner,
+            state: ModeState::new(cipher, mode, iv),
+            pending: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            offset: 0,
+            position: 0,
+            skip: 0,
+            eof: false,
+            done: false,
+        }
+    }
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+    /** Bytes of ciphertext which must be kept until the end of stream */
+    fn holdback(&self) -> usize {
+        match self.state.mode {
+            Mode::Cbc | Mode::CtrCmac => C::BLOCK_SIZE,
+            Mode::Ctr => 0,
+        }
+    }
+    fn fill(&mut self) -> io::Result<()> {
+        let block_size = C::BLOCK_SIZE;
+        self.out.clear();
+        self.out_pos = 0;
+
+        while self.out.is_empty() && !self.done {
+            let len = self.pending.len().saturating_sub(self.holdback()) / block_size * block_size;
+
+            if len > 0 {
+                self.out = self.pending.drain(..len).collect();
+                self.state.decrypt(&mut self.out);
+            } else if self.eof {
+                self.out = self.finalize()?;
+                self.done = true;
+            } else {
+                let mut chunk = vec![0; 16 * block_size];
+                let n = self.inner.read(&mut chunk)?;
+                if n == 0 {
+                    self.eof = true;
+                }
+                self.pending.extend_from_slice(&chunk[..n]);
+                self.offset += n as u64;
+            }
+        }
+
+        self.out_pos = self.skip.min(self.out.len());
+        self.skip -= self.out_pos;
+
+        Ok(())
+    }
+    /** Decrypt the held back data at the end of stream */
+    fn finalize(&mut self) -> io::Result<Vec<u8>> {
+        let mut last = std::mem::take(&mut self.pending);
+
+        match self.state.mode {
+            Mode::Cbc => {
+                if last.len() != C::BLOCK_SIZE {
+                    return Err(Error::Truncated.into());
+                }
+                self.state.decrypt(&mut last);
+
+                let pad = last[C::BLOCK_SIZE - 1] as usize;
+                if pad == 0
+                    || pad > C::BLOCK_SIZE
+                    || last[C::BLOCK_SIZE - pad..]
+                        .iter()
+                        .any(|byte| *byte as usize != pad)
+                {
+                    return Err(Error::Padding.into());
+                }
+                last.truncate(C::BLOCK_SIZE - pad);
+            }
+            Mode::Ctr => self.state.decrypt(&mut last),
+            Mode::CtrCmac => {
+                if last.len() < C::BLOCK_SIZE {
+                    return Err(Error::Truncated.into());
+                }
+                let tag = last.split_off(last.len() - C::BLOCK_SIZE);
+                self.state.decrypt(&mut last);
+
+                if !self.state.mac.take().is_some_and(|mac| mac.verify(&tag)) {
+                    return Err(Error::Authentication.into());
+                }
+            }
+        }
+
+        Ok(last)
+    }
+}
+
+impl<C: BlockCipher, R: Read> Read for DecryptReader<C, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.out_pos == self.out.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let len = buf.len().min(self.out.len() - self.out_pos);
+        buf[..len].copy_from_slice(&self.out[self.out_pos..self.out_pos + len]);
+        self.out_pos += len;
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+/** Random access into a [`Mode::Ctr`] stream, other modes return [`io::ErrorKind::Unsupported`] */
+impl<C: BlockCipher, R: Read + Seek> Seek for DecryptReader<C, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if self.state.mode != Mode::Ctr {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only CTR streams are seekable",
+            ));
+        }
+
+        let start = self.inner.stream_position()? - self.offset;
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                (self.inner.seek(SeekFrom::End(0))? - start).checked_add_signed(delta)
+            }
+        }
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+
+        let block_size = C::BLOCK_SIZE as u64;
+        self.state.counter = target / block_size;
+        self.offset = self.state.counter * block_size;
+        self.inner.seek(SeekFrom::Start(start + self.offset))?;
+
+        self.pending.clear();
+        self.out.clear();
+        self.out_pos = 0;
+        self.skip = (target % block_size) as usize;
+        self.position = target;
+        self.eof = false;
+        self.done = false;
+
+        Ok(target)
+    }
+}
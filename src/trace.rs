@@ -0,0 +1,118 @@
+#[cfg(feature = "alloc")]
+use alloc::{format, string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::fmt::{self, Write};
+
+use crate::modes::BlockCipher;
+
+/** Step of a round, decryption reports the inverse steps in reverse order */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    ShiftColumns,
+    SubstituteBytes,
+    ApplyRoundKey,
+}
+
+/** Receiver of intermediate values, see `Cipher*::encrypt_traced`
+ *
+ * The state is passed as rows of the matrix. Every method does nothing by
+ * default, and `()` is the observer which ignores everything.
+ */
+pub trait Observer {
+    /** Called with the input block before the first round */
+    fn begin(&mut self, _decrypt: bool, _state: &[[u8; 8]]) {}
+    /** Called at the start of every round with its round key and the digest byte of each row's S-Box */
+    fn round_key(&mut self, _round: usize, _key: &[u8], _digests: &[u8]) {}
+    /** Called after every step */
+    fn step(&mut self, _round: usize, _step: Step, _state: &[[u8; 8]]) {}
+    /** Called with the output block after the last round */
+    fn end(&mut self, _state: &[[u8; 8]]) {}
+}
+
+impl Observer for () {}
+
+/** Variants which can be traced through slices, for code generic over `BlockCipher` */
+pub trait Traceable: BlockCipher {
+    const ROUNDS: usize;
+
+    fn encrypt_block_traced(&self, block: &mut [u8], observer: &mut dyn Observer);
+}
+
+/** Observer which records an annotated per-round listing
+ *
+ * The layout follows the intermediate value appendix of FIPS-197, every
+ * row of the state is printed as 16 hex digits.
+ */
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default)]
+pub struct Listing {
+    decrypt: bool,
+    text: String,
+}
+
+#[cfg(feature = "alloc")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(feature = "alloc")]
+fn rows(state: &[[u8; 8]]) -> String {
+    state
+        .iter()
+        .map(|row| hex(row))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(feature = "alloc")]
+impl Listing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn line(&mut self, round: Option<usize>, label: &str, value: &str) {
+        let round = match round {
+            Some(round) => format!("round[{round:2}]"),
+            None => " ".repeat(9),
+        };
+        writeln!(self.text, "{round}.{label:<8}{value}").unwrap();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Observer for Listing {
+    fn begin(&mut self, decrypt: bool, state: &[[u8; 8]]) {
+        self.decrypt = decrypt;
+        let bits = state.len() * 64;
+        let title = if decrypt { "DECRYPT" } else { "ENCRYPT" };
+        writeln!(self.text, "NarrowWay-{bits} {title}").unwrap();
+        self.line(None, if decrypt { "iinput" } else { "input" }, &rows(state));
+    }
+    fn round_key(&mut self, round: usize, key: &[u8], digests: &[u8]) {
+        let key: Vec<String> = key.chunks(8).map(hex).collect();
+        let digests: Vec<String> = digests.iter().map(|byte| format!("{byte:02x}")).collect();
+        let prefix = if self.decrypt { "i" } else { "" };
+
+        self.line(Some(round), &format!("{prefix}k_sch"), &key.join(" "));
+        self.line(Some(round), &format!("{prefix}digest"), &digests.join(" "));
+    }
+    fn step(&mut self, round: usize, step: Step, state: &[[u8; 8]]) {
+        let label = match step {
+            Step::ShiftColumns => "s_col",
+            Step::SubstituteBytes => "s_box",
+            Step::ApplyRoundKey => "k_app",
+        };
+        let prefix = if self.decrypt { "i" } else { "" };
+        self.line(Some(round), &format!("{prefix}{label}"), &rows(state));
+    }
+    fn end(&mut self, state: &[[u8; 8]]) {
+        let label = if self.decrypt { "ioutput" } else { "output" };
+        self.line(None, label, &rows(state));
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
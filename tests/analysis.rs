@@ -0,0 +1,325 @@
+use narrowway::analysis::avalanche::{self, Input};
+use narrowway::analysis::diffusion::{self, Diffusion, Weight};
+use narrowway::analysis::keys::{self, Classes, Weakness};
+use narrowway::analysis::related;
+use narrowway::analysis::sbox::{self, Summary};
+use narrowway::analysis::timing::{self, Welch};
+use narrowway::analysis::trail::{self, TransitionTable};
+use narrowway::error::Error;
+use narrowway::rng::SplitMix64;
+use narrowway::trace::{Observer, Step};
+use narrowway::{Cipher256, Cipher384, Cipher512, S0};
+
+#[test]
+fn s0_properties() {
+    let summary = Summary::new(&S0);
+
+    assert_eq!(summary.differential_uniformity, 4);
+    assert_eq!(summary.nonlinearity, 112);
+    assert_eq!(summary.algebraic_degree, 7);
+    assert_eq!(summary.fixed_points, [0]);
+    assert!(summary.opposite_fixed_points.is_empty());
+}
+
+#[test]
+fn tables_are_consistent() {
+    let ddt = sbox::ddt(&S0);
+    let lat = sbox::lat(&S0);
+
+    assert_eq!(ddt[0][0], 256);
+    assert_eq!(lat[0][0], 128);
+    for a in 1..256 {
+        assert_eq!(ddt[a].iter().map(|&n| n as u32).sum::<u32>(), 256);
+        assert_eq!(ddt[a][0], 0, "S0 is a permutation");
+        assert_eq!(lat[a][0], 0);
+        assert_eq!(lat[0][a], 0);
+    }
+}
+
+#[test]
+fn xor_with_constant_invariants() {
+    let ddt = sbox::ddt(&S0);
+    let lat = sbox::lat(&S0);
+
+    for c in [0x01, 0x33, 0x80, 0xff] {
+        let keyed = sbox::keyed(&S0, c);
+        let keyed_lat = sbox::lat(&keyed);
+
+        assert_eq!(sbox::ddt(&keyed), ddt);
+        for (a, row) in keyed_lat.iter().enumerate() {
+            for (b, &entry) in row.iter().enumerate() {
+                let sign = if (b as u8 & c).count_ones().is_multiple_of(2) {
+                    1
+                } else {
+                    -1
+                };
+                assert_eq!(entry, sign * lat[a][b], "c = {c:02x}, a = {a}, b = {b}");
+            }
+        }
+        assert_eq!(sbox::algebraic_degree(&keyed), 7);
+        assert!(
+            sbox::fixed_points(&keyed)
+                .iter()
+                .all(|&x| keyed[x as usize] == x)
+        );
+    }
+}
+
+#[test]
+fn f_matrix_is_invertible() {
+    let f = diffusion::f_matrix();
+    let f_inv = diffusion::f_inv_matrix();
+
+    assert_eq!(f.rank(), 64);
+    for i in 0..64 {
+        assert_eq!(f_inv.apply(f.column(i)), 1 << i);
+    }
+    assert_eq!(f.transpose().transpose(), f);
+}
+
+#[test]
+fn f_branch_numbers() {
+    assert_eq!(diffusion::differential_branch_number(Weight::Bytes), 2);
+    assert_eq!(diffusion::differential_branch_number(Weight::Bits), 3);
+    assert_eq!(diffusion::linear_branch_number(Weight::Bytes), 3);
+    assert_eq!(diffusion::linear_branch_number(Weight::Bits), 3);
+}
+
+#[test]
+fn full_diffusion_rounds() {
+    for (rows, full, fastest) in [(4, 5, 4), (6, 5, 4), (8, 5, 5)] {
+        let diffusion = Diffusion::new(rows).unwrap();
+        assert_eq!(
+            (diffusion.full(), diffusion.fastest()),
+            (full, fastest),
+            "{rows} rows"
+        );
+    }
+}
+
+#[derive(Default)]
+struct FirstShift(Vec<[u8; 8]>);
+
+impl Observer for FirstShift {
+    fn step(&mut self, round: usize, step: Step, state: &[[u8; 8]]) {
+        if round == 0 && step == Step::ShiftColumns {
+            self.0 = state.to_vec();
+        }
+    }
+}
+
+#[test]
+fn shift_columns_positions_match_the_cipher() {
+    let mut observer = FirstShift::default();
+    Cipher384::new([0; 48]).encrypt_traced(std::array::from_fn(|i| i as u8), &mut observer);
+
+    for row in 0..6 {
+        for col in 0..8 {
+            let (to_row, to_col) = diffusion::shift_columns_position(6, row, col);
+            assert_eq!(observer.0[to_row][to_col] as usize, row * 8 + col);
+        }
+    }
+}
+
+#[test]
+fn avalanche_statistics() {
+    let stats = avalanche::measure::<Cipher256>(Input::Plaintext, 16, 64, 1);
+    let again = avalanche::measure::<Cipher256>(Input::Plaintext, 16, 64, 1);
+
+    assert_eq!(stats.len(), Cipher256::ROUNDS);
+    assert_eq!(stats[3].probability, again[3].probability, "seeded");
+    assert!(!stats[0].complete());
+    assert!(stats[0].sac_deviation() == 0.5);
+    for late in &stats[8..] {
+        assert!((late.avalanche() - 0.5).abs() < 0.01);
+        assert!(late.sac_mean_deviation() < 0.12);
+    }
+
+    let keys = avalanche::measure::<Cipher256>(Input::Key, 8, 256, 1);
+    assert!((keys.last().unwrap().avalanche() - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn truncated_transitions_match_f() {
+    let f = diffusion::f_matrix();
+    let table = TransitionTable::differential();
+
+    for x in [
+        1u64,
+        0x80,
+        0x1_0000,
+        0xff00_0000_0000_0000,
+        0x0102_0304_0506_0708,
+    ] {
+        let pattern =
+            |x: u64| (0..8).fold(0u8, |m, i| m | (((x >> (8 * i)) & 0xff != 0) as u8) << i);
+        assert!(
+            table.is_possible(pattern(x), pattern(f.apply(x))),
+            "{x:016x}"
+        );
+    }
+    assert_eq!(table.outputs(0), [0]);
+    assert!(!table.outputs(1).contains(&0));
+}
+
+#[test]
+fn fewest_active_s_boxes() {
+    let differential = trail::min_active(&TransitionTable::differential(), 4, 3);
+    let linear = trail::min_active(&TransitionTable::linear(), 4, 3);
+
+    assert_eq!(
+        differential.iter().map(|t| t.active()).collect::<Vec<_>>(),
+        [1, 2, 5]
+    );
+    assert_eq!(
+        linear.iter().map(|t| t.active()).collect::<Vec<_>>(),
+        [1, 3, 6]
+    );
+    assert_eq!(differential[2].states.len(), 3);
+
+    /* trails grown backwards from their lightest state must still connect round by round */
+    let table = TransitionTable::differential();
+    let trails = trail::min_active(&table, 4, 5);
+    assert_eq!(trails[4].active(), 16);
+    for trail in &trails {
+        for pair in trail.states.windows(2) {
+            let mut shifted = [0u8; 4];
+            for (row, mask) in pair[0].iter().enumerate() {
+                for col in (0..8).filter(|col| (mask >> col) & 1 == 1) {
+                    let (row, col) = diffusion::shift_columns_position(4, row, col);
+                    shifted[row] |= 1 << col;
+                }
+            }
+            for (a, &b) in shifted.iter().zip(&pair[1]) {
+                assert!(table.is_possible(*a, b), "{:?}", trail.states);
+            }
+        }
+    }
+
+    assert_eq!(trail::extend_bounds(&[1, 2, 5], 6), [1, 2, 5, 6, 7, 10]);
+}
+
+#[test]
+fn digest_classes() {
+    let single = keys::digest_counts(1);
+    assert_eq!(single[0], 0);
+    assert_eq!(single[1], 2, "0 and 1 share a class");
+    assert!(single[2..].iter().all(|&n| n == 1));
+
+    let classes = Classes::new(8);
+    assert_eq!(classes.rows(), 1 << 64);
+    assert_eq!(classes.counts[0], 0);
+    assert!(classes.largest() - classes.smallest() <= 1);
+    assert!((classes.collision_entropy() - 255f64.log2()).abs() < 1e-9);
+}
+
+#[test]
+fn weak_keys_are_rejected() {
+    /* the same bytes in every row of the first round key give identical S-Boxes */
+    let key = Cipher256::recover_key([7; 32], 0);
+    assert!(matches!(
+        Cipher256::new_checked(key),
+        Err(Error::WeakKey(Weakness::IdenticalRows { round: 0 }))
+    ));
+    let key = Cipher512::recover_key([0x5a; 64], 19);
+    assert!(matches!(
+        Cipher512::new_checked(key),
+        Err(Error::WeakKey(Weakness::IdenticalRows { round: 19 }))
+    ));
+
+    /* the heap-free check agrees and needs no `Error` */
+    let key = Cipher256::recover_key([7; 32], 0);
+    assert!(matches!(
+        Cipher256::try_new(key),
+        Err(Weakness::IdenticalRows { round: 0 })
+    ));
+
+    /* a repeated byte alternates with zero in the prefix XOR of the schedule */
+    assert!(Cipher256::new_checked([7; 32]).is_err());
+
+    let mut rng = SplitMix64::new(40);
+    let mut key = [0; 48];
+    rng.fill_bytes(&mut key);
+    assert!(Cipher384::new_checked(key).is_ok());
+    assert!(Cipher384::try_new(key).is_ok());
+
+    let digests = [[1, 2, 3], [1, 4, 5], [1, 6, 7]];
+    assert_eq!(
+        keys::weaknesses(&digests),
+        [Weakness::ConstantRow { row: 0 }]
+    );
+    assert_eq!(keys::check(&digests), Err(Weakness::ConstantRow { row: 0 }));
+}
+
+#[test]
+fn related_key_differences() {
+    /* the schedule is a prefix XOR, a difference in the last byte never spreads */
+    let mut delta = [0; 32];
+    delta[31] = 1;
+    let last = related::key_difference::<Cipher256>(&delta, 32, 1);
+    assert_eq!(last.len(), Cipher256::ROUNDS);
+    assert!(last.iter().all(|round| round.key_bytes == 1.0));
+    assert!(last.iter().all(|round| round.equal_digests >= 0.75));
+
+    delta = [0; 32];
+    delta[0] = 1;
+    let first = related::key_difference::<Cipher256>(&delta, 32, 1);
+    assert_eq!(first[0].key_bytes, 32.0);
+    assert!(first[1].fixed < 0.5);
+}
+
+#[test]
+fn slid_keys_part_after_one_round() {
+    let slid = related::slid_keys::<Cipher384>(16, 2);
+
+    assert_eq!(slid.len(), Cipher384::ROUNDS - 1);
+    assert_eq!(slid[0].key_bytes, 0.0);
+    assert_eq!(slid[0].equal_digests, 1.0);
+    assert_eq!(slid[0].agreeing_states, 1.0);
+    /* the round constants differ in every byte of the next round key */
+    assert_eq!(slid[1].key_bytes, 48.0);
+    assert_eq!(slid[1].agreeing_states, 0.0);
+}
+
+#[test]
+fn welch_t_statistic() {
+    let mut welch = Welch::new();
+    for x in [1.0, 2.0, 3.0, 4.0] {
+        welch.push(0, x);
+    }
+    for x in [3.0, 4.0, 5.0, 6.0] {
+        welch.push(1, x);
+    }
+    assert_eq!(welch.count(0), 4);
+    assert_eq!(welch.mean(1), 4.5);
+    /* both variances are 5/3, so t = -2 / sqrt(5/6) */
+    assert!((welch.t() + 2.0 / (5.0f64 / 6.0).sqrt()).abs() < 1e-12);
+
+    let mut same = Welch::new();
+    same.push(0, 1.0);
+    assert_eq!(same.t(), 0.0);
+}
+
+#[test]
+fn timing_separates_classes() {
+    /* the random class takes far longer, which any clock must see */
+    let leakage = timing::measure(
+        200,
+        0,
+        |random, _| random,
+        |&random| {
+            if random {
+                std::thread::sleep(std::time::Duration::from_micros(200));
+            }
+        },
+    );
+    assert!(leakage.leaks());
+    assert!(leakage.mean[1] > leakage.mean[0]);
+
+    let report = timing::Report::new::<Cipher256>(100, 0);
+    assert_eq!(report.operations.len(), 3);
+    assert_eq!(report.reference.len(), 3);
+    let printed = report.to_string();
+    assert!(printed.contains(timing::backend()));
+    assert!(printed.contains(timing::REFERENCE_BACKEND));
+}
@@ -0,0 +1,180 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("narrowway-cli-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn narrowway(args: &[&str], stdin: &[u8], env: &[(&str, &str)]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_narrowway"))
+        .args(args)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn key_file_pipeline() {
+    let dir = temp_dir("key");
+    let key = dir.join("key");
+    let key = key.to_str().unwrap();
+    let plaintext: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+
+    assert!(
+        narrowway(&["keygen", "-v", "512", "-o", key], &[], &[])
+            .status
+            .success()
+    );
+    assert_eq!(std::fs::read_to_string(key).unwrap().trim().len(), 128);
+
+    let args = ["-v", "512", "-k", key, "--chunk-size", "1000"];
+    let encrypted = narrowway(&[&["encrypt"], &args[..]].concat(), &plaintext, &[]);
+    assert!(encrypted.status.success());
+
+    let decrypted = narrowway(&["decrypt", "-k", key], &encrypted.stdout, &[]);
+    assert_eq!(decrypted.status.code(), Some(0));
+    assert_eq!(decrypted.stdout, plaintext);
+
+    let info = narrowway(&["info"], &encrypted.stdout, &[]);
+    assert!(
+        String::from_utf8(info.stdout)
+            .unwrap()
+            .contains("NarrowWay-512")
+    );
+
+    let mut tampered = encrypted.stdout.clone();
+    tampered[2000] ^= 1;
+    assert_eq!(
+        narrowway(&["decrypt", "-k", key], &tampered, &[])
+            .status
+            .code(),
+        Some(3)
+    );
+
+    for mode in ["ctr", "cbc"] {
+        let encrypted = narrowway(
+            &["encrypt", "-m", mode, "-v", "512", "-k", key],
+            &plaintext,
+            &[],
+        );
+        let decrypted = narrowway(
+            &["decrypt", "-m", mode, "-v", "512", "-k", key],
+            &encrypted.stdout,
+            &[],
+        );
+        assert_eq!(decrypted.stdout, plaintext);
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn passphrase_pipeline() {
+    let env = [("NW_TEST_PASSPHRASE", "hunter2")];
+    let args = [
+        "--passphrase-env",
+        "NW_TEST_PASSPHRASE",
+        "--iterations",
+        "8",
+    ];
+
+    let encrypted = narrowway(&[&["encrypt"], &args[..]].concat(), b"secret", &env);
+    let decrypted = narrowway(
+        &[&["decrypt"], &args[..2]].concat(),
+        &encrypted.stdout,
+        &env,
+    );
+    assert_eq!(decrypted.stdout, b"secret");
+
+    let wrong = [("NW_TEST_PASSPHRASE", "hunter3")];
+    let failed = narrowway(
+        &[&["decrypt"], &args[..2]].concat(),
+        &encrypted.stdout,
+        &wrong,
+    );
+    assert_eq!(failed.status.code(), Some(3));
+}
+
+#[test]
+fn exit_codes() {
+    let dir = temp_dir("exit");
+    let key = dir.join("key");
+    std::fs::write(&key, "00".repeat(32)).unwrap();
+    let key = key.to_str().unwrap();
+
+    assert_eq!(narrowway(&["frobnicate"], &[], &[]).status.code(), Some(1));
+    assert_eq!(narrowway(&["encrypt"], &[], &[]).status.code(), Some(1));
+    for (option, value) in [
+        ("--iterations", "0"),
+        ("--chunk-size", "0"),
+        ("--chunk-size", "16777217"),
+    ] {
+        let output = narrowway(&["encrypt", "-k", key, option, value], b"data", &[]);
+        assert_eq!(output.status.code(), Some(1), "{option} {value}");
+        assert!(output.stdout.is_empty());
+    }
+    let signed = dir.join("signed");
+    std::fs::write(&signed, "+f".repeat(32)).unwrap();
+    assert_eq!(
+        narrowway(&["encrypt", "-k", signed.to_str().unwrap()], b"data", &[])
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(
+        narrowway(&["decrypt", "-k", key, "-i", "/nonexistent/file"], &[], &[])
+            .status
+            .code(),
+        Some(2)
+    );
+    assert_eq!(
+        narrowway(&["decrypt", "-k", key], b"not a container", &[])
+            .status
+            .code(),
+        Some(4)
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn block_matches_examples() {
+    let zero = "00".repeat(48);
+    let expected = "d87c12170b2786b88ac8f1cbb8af7fab52ec35241f9a9a6f76e9844e0aaec16a\
+                    580f2b7c9d413e564c0993c075081dec";
+
+    let encrypted = narrowway(
+        &["block", "encrypt", "-v", "384", "--key", &zero, &zero],
+        &[],
+        &[],
+    );
+    assert_eq!(
+        String::from_utf8(encrypted.stdout).unwrap().trim(),
+        expected
+    );
+
+    let decrypted = narrowway(
+        &["block", "decrypt", "-v", "384", "--key", &zero, expected],
+        &[],
+        &[],
+    );
+    assert_eq!(String::from_utf8(decrypted.stdout).unwrap().trim(), zero);
+
+    let short = narrowway(&["block", "encrypt", "--key", &zero, "00"], &[], &[]);
+    assert_eq!(short.status.code(), Some(1));
+}
+
+#[test]
+fn vectors_regenerate_examples() {
+    let examples = narrowway(&["vectors", "examples"], &[], &[]);
+    let doc = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/doc/Examples.typ")).unwrap();
+    assert_eq!(examples.stdout, doc);
+}
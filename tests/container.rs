@@ -0,0 +1,240 @@
+use narrowway::container::{Header, Kdf, Reader, Variant, Writer};
+use narrowway::error::Error;
+use narrowway::{BlockCipher, Cipher256, Cipher384, Cipher512};
+use std::io::{Read, Write};
+
+const PASSPHRASE: &[u8] = b"correct horse battery staple";
+
+fn plaintext(len: usize) -> Vec<u8> {
+    b"NarrowWay container golden file. "
+        .iter()
+        .copied()
+        .cycle()
+        .take(len)
+        .collect()
+}
+
+fn counting(len: usize, start: u8) -> Vec<u8> {
+    (0..len).map(|i| start.wrapping_add(i as u8)).collect()
+}
+
+fn seal<C: BlockCipher>(key: &[u8], kdf: Kdf, chunk_size: u32, plaintext: &[u8]) -> Vec<u8> {
+    let nonce = counting(C::BLOCK_SIZE - 16, 0xa0);
+    let mut writer = Writer::<C, _>::new(key, kdf, &nonce, chunk_size, Vec::new()).unwrap();
+    writer.write_all(plaintext).unwrap();
+    writer.finish().unwrap()
+}
+
+fn open<C: BlockCipher>(secret: &[u8], file: &[u8]) -> Result<(Header, Vec<u8>), Error> {
+    let mut input = file;
+    let header = Header::read_from(&mut input)?;
+    let key = header.kdf.derive_key::<C>(secret)?;
+    let mut reader = Reader::<C, _>::new(header.clone(), &key, input)?;
+
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok((header, plaintext))
+}
+
+fn pbkdf() -> Kdf {
+    Kdf::Pbkdf {
+        iterations: 16,
+        salt: counting(16, 0).try_into().unwrap(),
+    }
+}
+
+/** 200 bytes in 64-byte chunks */
+fn golden_256() -> Vec<u8> {
+    seal::<Cipher256>(&counting(32, 0), Kdf::Raw, 64, &plaintext(200))
+}
+
+/** exactly one full chunk */
+fn golden_384() -> Vec<u8> {
+    seal::<Cipher384>(&counting(48, 0), Kdf::Raw, 100, &plaintext(100))
+}
+
+fn golden_512() -> Vec<u8> {
+    let key = pbkdf().derive_key::<Cipher512>(PASSPHRASE).unwrap();
+    seal::<Cipher512>(&key, pbkdf(), 4096, &plaintext(37))
+}
+
+#[test]
+fn golden_files() {
+    let cases = [
+        (
+            include_bytes!("data/container/nw256-raw.nwe").as_slice(),
+            golden_256(),
+        ),
+        (
+            include_bytes!("data/container/nw384-raw.nwe").as_slice(),
+            golden_384(),
+        ),
+        (
+            include_bytes!("data/container/nw512-pbkdf.nwe").as_slice(),
+            golden_512(),
+        ),
+    ];
+
+    for (golden, fresh) in &cases {
+        assert_eq!(*golden, fresh.as_slice());
+    }
+
+    let (header, data) = open::<Cipher256>(&counting(32, 0), cases[0].0).unwrap();
+    assert_eq!(header.variant, Variant::Nw256);
+    assert_eq!(header.chunk_size, 64);
+    assert_eq!(data, plaintext(200));
+
+    let (_, data) = open::<Cipher384>(&counting(48, 0), cases[1].0).unwrap();
+    assert_eq!(data, plaintext(100));
+
+    let (header, data) = open::<Cipher512>(PASSPHRASE, cases[2].0).unwrap();
+    assert_eq!(header.kdf, pbkdf());
+    assert_eq!(data, plaintext(37));
+}
+
+#[test]
+fn empty_plaintext() {
+    let key = counting(32, 7);
+    let file = seal::<Cipher256>(&key, Kdf::Raw, 16, &[]);
+
+    assert_eq!(file.len(), Header::size(Variant::Nw256) + 32);
+    assert!(open::<Cipher256>(&key, &file).unwrap().1.is_empty());
+}
+
+#[test]
+fn header_parsing_is_strict() {
+    let key = counting(32, 0);
+    let file = golden_256();
+
+    let mut bad = file.clone();
+    bad[0] = b'X';
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::BadMagic)
+    ));
+
+    let mut bad = file.clone();
+    bad[4] = 2;
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::UnsupportedVersion(2))
+    ));
+
+    let mut bad = file.clone();
+    bad[5] = 9;
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::UnsupportedVariant(9))
+    ));
+
+    let mut bad = file.clone();
+    bad[6] = 2;
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::UnsupportedMode(2))
+    ));
+
+    let mut bad = file.clone();
+    bad[8] = 1;
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::InvalidHeader(_))
+    ));
+
+    let mut bad = file.clone();
+    bad[28..32].copy_from_slice(&[0; 4]);
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::InvalidHeader(_))
+    ));
+
+    assert!(matches!(
+        open::<Cipher256>(&key, &file[..20]),
+        Err(Error::Truncated)
+    ));
+    assert!(matches!(
+        open::<Cipher256>(&counting(32, 1), &file),
+        Err(Error::WrongKey)
+    ));
+}
+
+#[test]
+fn writer_only_writes_readable_headers() {
+    let key = counting(32, 0);
+    let nonce = counting(16, 0xa0);
+
+    for kdf in [
+        Kdf::Raw,
+        pbkdf(),
+        Kdf::Pbkdf {
+            iterations: 1,
+            salt: [0; 16],
+        },
+    ] {
+        let file = seal::<Cipher256>(&key, kdf.clone(), 64, b"header");
+        assert_eq!(Header::read_from(&mut &file[..]).unwrap().kdf, kdf);
+    }
+
+    let mut output = Vec::new();
+    let zero = Kdf::Pbkdf {
+        iterations: 0,
+        salt: [1; 16],
+    };
+    assert!(matches!(
+        Writer::<Cipher256, _>::new(&key, zero, &nonce, 64, &mut output),
+        Err(Error::InvalidHeader("zero KDF iterations"))
+    ));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn tampering_is_detected() {
+    let key = counting(32, 0);
+    let file = golden_256();
+    let header = Header::size(Variant::Nw256);
+    let chunk = 64 + 32;
+
+    /* header fields are authenticated through every chunk */
+    let mut bad = file.clone();
+    bad[28..32].copy_from_slice(&65u32.to_be_bytes());
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::Authentication)
+    ));
+
+    let mut bad = file.clone();
+    bad[header + 5] ^= 1;
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::Authentication)
+    ));
+
+    /* reorder the first two chunks */
+    let mut bad = file[..header].to_vec();
+    bad.extend_from_slice(&file[header + chunk..header + 2 * chunk]);
+    bad.extend_from_slice(&file[header..header + chunk]);
+    bad.extend_from_slice(&file[header + 2 * chunk..]);
+    assert!(matches!(
+        open::<Cipher256>(&key, &bad),
+        Err(Error::Authentication)
+    ));
+
+    /* drop the final chunk */
+    let bad = &file[..header + 2 * chunk];
+    assert!(matches!(
+        open::<Cipher256>(&key, bad),
+        Err(Error::Truncated)
+    ));
+
+    let bad = &file[..file.len() - 1];
+    assert!(matches!(
+        open::<Cipher256>(&key, bad),
+        Err(Error::Authentication)
+    ));
+
+    let bad = &file[..header + 10];
+    assert!(matches!(
+        open::<Cipher256>(&key, bad),
+        Err(Error::Truncated)
+    ));
+}
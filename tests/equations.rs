@@ -0,0 +1,121 @@
+use narrowway::analysis::equations::{self, Cnf, System};
+use narrowway::rng::SplitMix64;
+use narrowway::trace::{Observer, Step};
+use narrowway::{Cipher256, Cipher384};
+
+/** State after the first `rounds` rounds of the full cipher */
+struct After {
+    rounds: usize,
+    state: Vec<u8>,
+}
+
+impl Observer for After {
+    fn step(&mut self, round: usize, step: Step, state: &[[u8; 8]]) {
+        if round + 1 == self.rounds && step == Step::ApplyRoundKey {
+            self.state = state.as_flattened().to_vec();
+        }
+    }
+}
+
+#[test]
+fn symbolic_plaintext_matches_the_cipher() {
+    let mut rng = SplitMix64::new(41);
+    let mut key = [0; 48];
+    rng.fill_bytes(&mut key);
+    let cipher = Cipher384::new(key);
+
+    let mut mask = [0; 48];
+    mask[5] = 0xff;
+    mask[30] = 0x81;
+    let system = System::encryption(2, &key, &[0; 48], &[0; 48], &mask);
+    assert_eq!(system.inputs().len(), 10);
+
+    for _ in 0..8 {
+        let inputs: Vec<bool> = (0..10).map(|_| rng.next_u64() & 1 == 1).collect();
+        let assignment = system.evaluate(&inputs);
+        let plaintext = equations::bytes_of(&system.plaintext, &assignment);
+
+        let mut after = After {
+            rounds: 2,
+            state: Vec::new(),
+        };
+        cipher.encrypt_traced(plaintext.try_into().unwrap(), &mut after);
+        assert_eq!(
+            equations::bytes_of(&system.ciphertext, &assignment),
+            after.state
+        );
+    }
+}
+
+#[test]
+fn key_recovery_round_trips_through_cnf_and_anf() {
+    let mut rng = SplitMix64::new(41);
+    let mut key = [0; 32];
+    let mut plaintext = [0; 32];
+    rng.fill_bytes(&mut key);
+    rng.fill_bytes(&mut plaintext);
+
+    let mut after = After {
+        rounds: 1,
+        state: Vec::new(),
+    };
+    Cipher256::new(key).encrypt_traced(plaintext, &mut after);
+
+    let mut key_mask = [0; 32];
+    key_mask[3] = 0b1001_0000;
+    key_mask[20] = 0b0000_0111;
+    let mut system = System::encryption(1, &key, &key_mask, &plaintext, &[0; 32]);
+    let ciphertext = system.ciphertext.clone();
+    system.fix(&ciphertext, &after.state);
+
+    let cnf = Cnf::parse(&system.to_dimacs()).unwrap();
+    let anf = equations::parse_anf(&system.to_anf()).unwrap();
+    assert_eq!(cnf.variables, system.variables());
+    assert_eq!(cnf.clauses, system.clauses());
+    assert_eq!(anf, system.polynomials());
+
+    let by_cnf = system.solutions(|assignment| cnf.satisfied(assignment));
+    let by_anf = system.solutions(|assignment| anf.iter().all(|p| !p.evaluate(assignment)));
+    assert_eq!(by_cnf, by_anf);
+    assert!(!by_cnf.is_empty());
+    for solution in &by_cnf {
+        let assignment = system.evaluate(solution);
+        let recovered: [u8; 32] = equations::bytes_of(&system.key, &assignment)
+            .try_into()
+            .unwrap();
+        let mut check = After {
+            rounds: 1,
+            state: Vec::new(),
+        };
+        Cipher256::new(recovered).encrypt_traced(plaintext, &mut check);
+        assert_eq!(check.state, after.state);
+    }
+
+    /* every defined variable is pinned down by its gate */
+    let inputs: Vec<bool> = (0..256)
+        .filter(|i| (key_mask[i / 8] >> (i % 8)) & 1 == 1)
+        .map(|i| (key[i / 8] >> (i % 8)) & 1 == 1)
+        .collect();
+    assert!(by_cnf.contains(&inputs));
+    let truth = system.evaluate(&inputs);
+    assert_eq!(equations::bytes_of(&system.key, &truth), key);
+    let inputs = system.inputs();
+    for v in (0..system.variables()).filter(|v| !inputs.contains(v)) {
+        let mut flipped = truth.clone();
+        flipped[v] = !flipped[v];
+        assert!(!cnf.satisfied(&flipped), "x{v} is free in the CNF");
+        assert!(
+            anf.iter().any(|p| p.evaluate(&flipped)),
+            "x{v} is free in the ANF"
+        );
+    }
+
+    let mut wrong = system.clone();
+    wrong.fix(&ciphertext, &[0; 32]);
+    let cnf = Cnf::parse(&wrong.to_dimacs()).unwrap();
+    assert!(
+        wrong
+            .solutions(|assignment| cnf.satisfied(assignment))
+            .is_empty()
+    );
+}
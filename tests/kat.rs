@@ -0,0 +1,211 @@
+/*!
+ * Known-answer tests against `doc/Examples.typ` and the files in `tests/data/kat`,
+ * which are written by `narrowway vectors kat`.
+ *
+ * Every vector is checked against `Cipher*` and against `reference::Cipher`,
+ * which is built only on the on-the-fly GF(2^8) arithmetic the `poc` feature
+ * switches to, so the default run already shows that the tables and that
+ * arithmetic agree on all of them. With `--features poc`, `Cipher*` itself
+ * computes every S-Box on the fly and is checked on every 64th entry only.
+ */
+
+use narrowway::reference;
+use narrowway::{BlockCipher, Cipher256, Cipher384, Cipher512};
+
+const STRIDE: usize = if cfg!(feature = "poc") { 64 } else { 1 };
+
+#[derive(Debug)]
+struct Vector {
+    key: Vec<u8>,
+    plaintext: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+fn decode(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn parse_rsp(text: &str) -> Vec<Vector> {
+    let mut vectors = Vec::new();
+    let mut fields = [None, None, None];
+
+    for line in text.lines() {
+        let Some((name, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        let slot = match name {
+            "KEY" => 0,
+            "PLAINTEXT" => 1,
+            "CIPHERTEXT" => 2,
+            _ => continue,
+        };
+        fields[slot] = Some(decode(value));
+
+        if let [Some(_), Some(_), Some(_)] = &fields {
+            let [key, plaintext, ciphertext] = fields.map(Option::unwrap);
+            vectors.push(Vector {
+                key,
+                plaintext,
+                ciphertext,
+            });
+            fields = [None, None, None];
+        }
+    }
+
+    vectors
+}
+
+fn check<C: BlockCipher>(name: &str, vectors: &[Vector]) {
+    for (i, vector) in vectors.iter().enumerate().step_by(STRIDE) {
+        let cipher = C::new_from_slice(&vector.key);
+
+        let mut block = vector.plaintext.clone();
+        cipher.encrypt_block(&mut block);
+        assert_eq!(block, vector.ciphertext, "{name} COUNT = {i} encryption");
+
+        cipher.decrypt_block(&mut block);
+        assert_eq!(block, vector.plaintext, "{name} COUNT = {i} decryption");
+    }
+}
+
+/** Every vector against `reference::Cipher`, whatever backend this build uses */
+fn check_reference(name: &str, vectors: &[Vector]) {
+    let mut cipher = reference::Cipher::new(&vectors[0].key);
+    let mut key = &vectors[0].key;
+
+    for (i, vector) in vectors.iter().enumerate() {
+        /* VarTxt files keep one key, which is expensive to schedule here */
+        if vector.key != *key {
+            cipher = reference::Cipher::new(&vector.key);
+            key = &vector.key;
+        }
+
+        assert_eq!(
+            cipher.encrypt(&vector.plaintext),
+            vector.ciphertext,
+            "{name} COUNT = {i} reference encryption"
+        );
+        assert_eq!(
+            cipher.decrypt(&vector.ciphertext),
+            vector.plaintext,
+            "{name} COUNT = {i} reference decryption"
+        );
+    }
+}
+
+macro_rules! kat_file {
+    ($test: ident, $reference: ident, $cipher: ty, $file: literal, $count: expr) => {
+        #[test]
+        fn $test() {
+            let vectors = parse_rsp(include_str!(concat!("data/kat/", $file)));
+            assert_eq!(vectors.len(), $count);
+            check::<$cipher>($file, &vectors);
+        }
+
+        #[test]
+        fn $reference() {
+            let vectors = parse_rsp(include_str!(concat!("data/kat/", $file)));
+            check_reference($file, &vectors);
+        }
+    };
+}
+
+kat_file!(
+    var_key_256,
+    var_key_256_reference,
+    Cipher256,
+    "NW256VarKey.rsp",
+    256
+);
+kat_file!(
+    var_txt_256,
+    var_txt_256_reference,
+    Cipher256,
+    "NW256VarTxt.rsp",
+    256
+);
+kat_file!(
+    random_256,
+    random_256_reference,
+    Cipher256,
+    "NW256Random.rsp",
+    100
+);
+kat_file!(
+    var_key_384,
+    var_key_384_reference,
+    Cipher384,
+    "NW384VarKey.rsp",
+    384
+);
+kat_file!(
+    var_txt_384,
+    var_txt_384_reference,
+    Cipher384,
+    "NW384VarTxt.rsp",
+    384
+);
+kat_file!(
+    random_384,
+    random_384_reference,
+    Cipher384,
+    "NW384Random.rsp",
+    100
+);
+kat_file!(
+    var_key_512,
+    var_key_512_reference,
+    Cipher512,
+    "NW512VarKey.rsp",
+    512
+);
+kat_file!(
+    var_txt_512,
+    var_txt_512_reference,
+    Cipher512,
+    "NW512VarTxt.rsp",
+    512
+);
+kat_file!(
+    random_512,
+    random_512_reference,
+    Cipher512,
+    "NW512Random.rsp",
+    100
+);
+
+/** Parse the `K`, `M` and `E(K, M)` values of every variant, joining wrapped lines */
+fn parse_examples(text: &str) -> Vec<Vector> {
+    let mut values: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some((_, value)) = line.split_once(" = 0x") {
+            values.push(value.to_string());
+        } else if !line.is_empty() && line.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            values.last_mut().unwrap().push_str(line);
+        }
+    }
+
+    values
+        .chunks(3)
+        .map(|chunk| Vector {
+            key: decode(&chunk[0]),
+            plaintext: decode(&chunk[1]),
+            ciphertext: decode(&chunk[2]),
+        })
+        .collect()
+}
+
+#[test]
+fn spec_examples() {
+    let vectors = parse_examples(include_str!("../doc/Examples.typ"));
+    assert_eq!(vectors.len(), 3);
+
+    check::<Cipher256>("NarrowWay-256 example", &vectors[0..1]);
+    check::<Cipher384>("NarrowWay-384 example", &vectors[1..2]);
+    check::<Cipher512>("NarrowWay-512 example", &vectors[2..3]);
+}
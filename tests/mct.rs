@@ -0,0 +1,187 @@
+/*!
+ * AESAVS-style Monte Carlo tests against the checkpoints in `tests/data/mct`,
+ * which are written by `narrowway vectors mct`.
+ *
+ * Key, IV, input and output are compared at every checkpoint reached. The
+ * default run covers only the first 10 outer iterations, so it reaches
+ * checkpoints 0 to 9; 99, 199, ... 999 are only checked by the complete
+ * 1000 x 1000 run, which is ignored by default and run by CI:
+ *
+ * ```text
+ * cargo test --release --test mct -- --ignored
+ * ```
+ */
+
+use narrowway::{BlockCipher, Cipher256, Cipher384, Cipher512};
+use std::collections::HashMap;
+
+const INNER: usize = 1000;
+
+#[derive(Debug, Default)]
+struct Checkpoint {
+    count: usize,
+    key: Vec<u8>,
+    iv: Option<Vec<u8>>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Section {
+    decrypt: bool,
+    checkpoints: Vec<Checkpoint>,
+}
+
+fn decode(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn parse(text: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        if let Some(name) = line.strip_prefix('[') {
+            sections.push(Section {
+                decrypt: name == "DECRYPT]",
+                checkpoints: Vec::new(),
+            });
+            continue;
+        }
+        let Some((name, value)) = line.split_once(" = ") else {
+            continue;
+        };
+
+        let section = sections.last_mut().unwrap();
+        if name == "COUNT" {
+            section.checkpoints.push(Checkpoint {
+                count: value.parse().unwrap(),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let checkpoint = section.checkpoints.last_mut().unwrap();
+        let (input, output) = match section.decrypt {
+            false => ("PLAINTEXT", "CIPHERTEXT"),
+            true => ("CIPHERTEXT", "PLAINTEXT"),
+        };
+        match name {
+            "KEY" => checkpoint.key = decode(value),
+            "IV" => checkpoint.iv = Some(decode(value)),
+            _ if name == input => checkpoint.input = decode(value),
+            _ if name == output => checkpoint.output = decode(value),
+            _ => panic!("unknown field {name}"),
+        }
+    }
+
+    sections
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+/** Run `outer` iterations from the first checkpoint and compare every checkpoint on the way */
+fn run<C: BlockCipher>(name: &str, section: &Section, outer: usize) {
+    let first = &section.checkpoints[0];
+    let expected: HashMap<usize, &Checkpoint> = section
+        .checkpoints
+        .iter()
+        .map(|checkpoint| (checkpoint.count, checkpoint))
+        .collect();
+
+    let mut key = first.key.clone();
+    let mut iv = first.iv.clone();
+    let mut input = first.input.clone();
+
+    for i in 0..outer {
+        let cipher = C::new_from_slice(&key);
+        let (last, second_last) = match &iv {
+            /* ECB: every output is the next input */
+            None => {
+                let mut block = input.clone();
+                for _ in 0..INNER {
+                    if section.decrypt {
+                        cipher.decrypt_block(&mut block);
+                    } else {
+                        cipher.encrypt_block(&mut block);
+                    }
+                }
+                (block, None)
+            }
+            /* CBC: the next input is the IV, then the output two steps back */
+            Some(iv) => {
+                let mut history = vec![iv.clone()];
+                let mut chain = iv.clone();
+                let mut block = input.clone();
+
+                for j in 0..INNER {
+                    let mut out = block.clone();
+                    if section.decrypt {
+                        cipher.decrypt_block(&mut out);
+                        out = xor(&out, &chain);
+                        chain = block.clone();
+                    } else {
+                        out = xor(&out, &chain);
+                        cipher.encrypt_block(&mut out);
+                        chain = out.clone();
+                    }
+                    block = history[j].clone();
+                    history.push(out);
+                }
+                (history.pop().unwrap(), history.pop())
+            }
+        };
+
+        if let Some(checkpoint) = expected.get(&i) {
+            assert_eq!(key, checkpoint.key, "{name} COUNT = {i} key");
+            assert_eq!(iv, checkpoint.iv, "{name} COUNT = {i} iv");
+            assert_eq!(input, checkpoint.input, "{name} COUNT = {i} input");
+            assert_eq!(last, checkpoint.output, "{name} COUNT = {i} output");
+        }
+
+        key = xor(&key, &last);
+        match second_last {
+            Some(second_last) => {
+                iv = Some(last);
+                input = second_last;
+            }
+            None => input = last,
+        }
+    }
+}
+
+fn check<C: BlockCipher>(name: &str, text: &str, outer: usize) {
+    let sections = parse(text);
+    assert_eq!(sections.len(), 2);
+
+    for section in &sections {
+        assert_eq!(section.checkpoints.len(), 20);
+        run::<C>(name, section, outer);
+    }
+}
+
+macro_rules! mct {
+    ($quick: ident, $full: ident, $cipher: ty, $file: literal) => {
+        #[test]
+        fn $quick() {
+            check::<$cipher>($file, include_str!(concat!("data/mct/", $file)), 10);
+        }
+
+        #[test]
+        #[ignore = "one million block operations, run in release mode"]
+        fn $full() {
+            check::<$cipher>($file, include_str!(concat!("data/mct/", $file)), 1000);
+        }
+    };
+}
+
+mct!(ecb_256, ecb_256_full, Cipher256, "NW256ECBMCT.rsp");
+mct!(cbc_256, cbc_256_full, Cipher256, "NW256CBCMCT.rsp");
+mct!(ecb_384, ecb_384_full, Cipher384, "NW384ECBMCT.rsp");
+mct!(cbc_384, cbc_384_full, Cipher384, "NW384CBCMCT.rsp");
+mct!(ecb_512, ecb_512_full, Cipher512, "NW512ECBMCT.rsp");
+mct!(cbc_512, cbc_512_full, Cipher512, "NW512CBCMCT.rsp");
@@ -0,0 +1,53 @@
+use narrowway::prp::Permutation;
+
+const KEY: [u8; 32] = [0x5a; 32];
+
+fn assert_bijection(perm: &Permutation, domain: u64) {
+    let mut seen = vec![false; domain as usize];
+
+    for x in 0..domain {
+        let y = perm.permute(x);
+        assert!(y < domain, "{x} maps to {y} outside of 0..{domain}");
+        assert!(!seen[y as usize], "{y} is hit twice in 0..{domain}");
+        seen[y as usize] = true;
+        assert_eq!(perm.invert(y), x);
+    }
+}
+
+#[test]
+fn bijection_on_small_domains() {
+    for domain in 1..=33 {
+        assert_bijection(&Permutation::new(KEY, domain), domain);
+    }
+    assert_bijection(&Permutation::new(KEY, 1000), 1000);
+}
+
+#[test]
+fn bijection_on_power_of_two_domains() {
+    for bits in 1..=8 {
+        assert_bijection(&Permutation::with_bits(KEY, bits), 1 << bits);
+    }
+}
+
+#[test]
+fn invert_full_width() {
+    let perm32 = Permutation::with_bits(KEY, 32);
+    let perm64 = Permutation::with_bits(KEY, 64);
+
+    for x in [0, 1, 2, 0xdead_beef, u32::MAX as u64] {
+        let y = perm32.permute(x);
+        assert!(y <= u32::MAX as u64);
+        assert_eq!(perm32.invert(y), x);
+    }
+    for x in [0, 1, 42, u64::MAX - 1, u64::MAX] {
+        assert_eq!(perm64.invert(perm64.permute(x)), x);
+    }
+}
+
+#[test]
+fn key_changes_permutation() {
+    let a = Permutation::new(KEY, 1 << 20);
+    let b = Permutation::new([0xa5; 32], 1 << 20);
+
+    assert!((0..16).any(|x| a.permute(x) != b.permute(x)));
+}
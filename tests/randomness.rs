@@ -0,0 +1,73 @@
+use narrowway::analysis::randomness::{self, ALPHA, Stream};
+use narrowway::{Cipher256, Cipher512};
+
+fn bits(text: &str) -> Vec<bool> {
+    text.bytes().map(|c| c == b'1').collect()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+/* examples of section 2 of NIST SP 800-22 rev. 1a */
+#[test]
+fn nist_examples() {
+    assert!(close(randomness::frequency(&bits("1011010101")), 0.527089));
+    assert!(close(
+        randomness::block_frequency(&bits("0110011010"), 3),
+        0.801252
+    ));
+    assert!(close(randomness::runs(&bits("1001101011")), 0.147232));
+    assert!(close(
+        randomness::longest_run(&bits(
+            "11001100000101010110110001001100111000000000001001001101010100010001\
+             001111010110100000001101011111001100111001101101100010110010"
+        )),
+        /* the document prints 0.180609, Q(3/2, 4.882605/2) is 0.180598 */
+        0.180598
+    ));
+
+    let [p1, p2] = randomness::serial(&bits("0011011101"), 3);
+    assert!(close(p1, 0.808792) && close(p2, 0.670320), "{p1} {p2}");
+    assert!(close(
+        randomness::approximate_entropy(&bits("0100110101"), 3),
+        0.261961
+    ));
+    let [forward, _] = randomness::cumulative_sums(&bits("1011010111"));
+    assert!(close(forward, 0.4116588), "{forward}");
+}
+
+#[test]
+fn special_functions() {
+    assert!(close(randomness::erfc(0.0), 1.0));
+    assert!(close(randomness::erfc(1.0), 0.157299));
+    assert!(close(randomness::erfc(-1.0), 1.842701));
+    assert!(close(randomness::igamc(1.0, 2.0), (-2.0f64).exp()));
+    assert!(close(
+        randomness::igamc(0.5, 0.5),
+        randomness::erfc(0.5f64.sqrt())
+    ));
+    assert!(close(randomness::igamc(3.0, 0.0), 1.0));
+}
+
+#[test]
+fn full_rounds_pass_and_one_round_fails() {
+    for stream in [Stream::Keystream, Stream::Correlation] {
+        let bits = stream.generate::<Cipher256>(Cipher256::ROUNDS, 1 << 15, 1);
+        for outcome in randomness::battery(&bits) {
+            /* a fixed seed keeps this deterministic, but leave room below ALPHA */
+            assert!(
+                outcome.min() > ALPHA / 10.0,
+                "{} {outcome:?}",
+                stream.name()
+            );
+        }
+    }
+
+    let bits = Stream::Keystream.generate::<Cipher512>(1, 1 << 15, 1);
+    let worst = randomness::battery(&bits)
+        .iter()
+        .map(|outcome| outcome.min())
+        .fold(1.0, f64::min);
+    assert!(worst < 1e-6, "{worst}");
+}
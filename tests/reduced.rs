@@ -0,0 +1,89 @@
+#![cfg(feature = "reduced-round")]
+
+use narrowway::reduced::{MAX_ROUNDS, Position, Reduced256, Reduced384, Reduced512, ReducedCipher};
+use narrowway::trace::Step;
+use narrowway::{BlockCipher, Cipher256, Cipher384, Cipher512};
+
+fn full_rounds_match<C: BlockCipher, const ROWS: usize>(rounds: usize) {
+    let key: Vec<u8> = (0..C::BLOCK_SIZE).map(|i| (i * 13) as u8).collect();
+    let reference = C::new_from_slice(&key);
+    let reduced = ReducedCipher::<ROWS>::new(&key, rounds);
+
+    let mut expected: Vec<u8> = (0..C::BLOCK_SIZE).map(|i| i as u8).collect();
+    let mut block = expected.clone();
+    reference.encrypt_block(&mut expected);
+    reduced.encrypt_block(&mut block);
+    assert_eq!(block, expected);
+
+    reduced.decrypt_block(&mut block);
+    assert_eq!(
+        block,
+        (0..C::BLOCK_SIZE).map(|i| i as u8).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn full_round_count_is_the_cipher() {
+    full_rounds_match::<Cipher256, 4>(Cipher256::ROUNDS);
+    full_rounds_match::<Cipher384, 6>(Cipher384::ROUNDS);
+    full_rounds_match::<Cipher512, 8>(Cipher512::ROUNDS);
+}
+
+#[test]
+fn every_round_count_inverts() {
+    let key = [0x42; 48];
+
+    for rounds in 1..=MAX_ROUNDS {
+        let cipher = Reduced384::new(&key, rounds);
+        let mut block = [rounds as u8; 48];
+        cipher.encrypt_block(&mut block);
+        assert_ne!(block, [rounds as u8; 48]);
+        cipher.decrypt_block(&mut block);
+        assert_eq!(block, [rounds as u8; 48], "{rounds} rounds");
+    }
+}
+
+#[test]
+fn spans_compose() {
+    let key = [9; 64];
+    let start = Position::new(1, Step::SubstituteBytes);
+    let middle = Position::new(3, Step::ApplyRoundKey);
+    let end = Position::new(5, Step::SubstituteBytes);
+
+    let mut whole = [0x17; 64];
+    Reduced512::span(&key, start, end).encrypt_block(&mut whole);
+
+    let mut split = [0x17; 64];
+    Reduced512::span(&key, start, middle).encrypt_block(&mut split);
+    Reduced512::span(&key, middle, end).encrypt_block(&mut split);
+    assert_eq!(split, whole);
+
+    Reduced512::span(&key, start, end).decrypt_block(&mut split);
+    assert_eq!(split, [0x17; 64]);
+}
+
+#[test]
+fn round_keys_continue_the_schedule() {
+    let key = [3; 32];
+    let reduced = Reduced256::new(&key, MAX_ROUNDS);
+    let expanded = Cipher256::expand_key(key);
+
+    assert_eq!(reduced.round_keys().len(), MAX_ROUNDS);
+    for (round, round_key) in expanded.iter().enumerate() {
+        assert_eq!(reduced.round_keys()[round].as_flattened(), round_key);
+    }
+}
+
+#[test]
+fn empty_span_is_identity() {
+    let position = Position::new(2, Step::ApplyRoundKey);
+    let mut block = [5; 32];
+    Reduced256::span(&[0; 32], position, position).encrypt_block(&mut block);
+    assert_eq!(block, [5; 32]);
+}
+
+#[test]
+#[should_panic(expected = "at most")]
+fn too_many_rounds() {
+    Reduced256::new(&[0; 32], MAX_ROUNDS + 1);
+}
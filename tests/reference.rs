@@ -0,0 +1,99 @@
+#[cfg(not(feature = "compact"))]
+use narrowway::GF28_TABLE;
+use narrowway::modes::BlockCipher;
+use narrowway::reference::{self, Cipher};
+use narrowway::rng::SplitMix64;
+use narrowway::{Cipher256, Cipher384, Cipher512, GF28_EXP, GF28_INV, GF28_LOG, RC, S0};
+
+const GF28_M: u8 = 0b1110001;
+
+#[cfg(not(feature = "compact"))]
+#[test]
+fn gf_mul_matches_table() {
+    for f in 0..=255u8 {
+        for g in 0..=255u8 {
+            assert_eq!(
+                reference::gf_mul(f, g, GF28_M),
+                GF28_TABLE[f as usize][g as usize],
+                "{f:02x} * {g:02x}"
+            );
+        }
+    }
+}
+
+#[test]
+fn gf_mul_matches_log_tables() {
+    for f in 1..=255u8 {
+        for g in 1..=255u8 {
+            let log = GF28_LOG[f as usize] as usize + GF28_LOG[g as usize] as usize;
+            assert_eq!(reference::gf_mul(f, g, GF28_M), GF28_EXP[log % 255]);
+        }
+    }
+    for i in 0..255 {
+        assert_eq!(GF28_LOG[GF28_EXP[i] as usize] as usize, i);
+    }
+}
+
+#[test]
+fn gf_mul_inv_matches_table() {
+    for f in 0..=255u8 {
+        assert_eq!(reference::gf_mul_inv(f, GF28_M), GF28_INV[f as usize]);
+    }
+}
+
+#[test]
+fn s0_matches_table() {
+    assert_eq!(reference::s0_gen(), S0);
+    for i in 0..=255u8 {
+        assert_eq!(
+            reference::bit_transform(reference::gf_mul_inv(i, GF28_M)),
+            S0[i as usize]
+        );
+    }
+}
+
+#[test]
+fn rc_matches_table() {
+    for (i, &rc) in RC.iter().enumerate() {
+        assert_eq!(reference::rc(i), rc, "round {i}");
+    }
+}
+
+fn compare<C: BlockCipher>(seed: u64, keys: usize, blocks: usize) {
+    let mut rng = SplitMix64::new(seed);
+
+    for _ in 0..keys {
+        let mut key = vec![0; C::BLOCK_SIZE];
+        rng.fill_bytes(&mut key);
+        let tables = C::new_from_slice(&key);
+        let reference = Cipher::new(&key);
+
+        for _ in 0..blocks {
+            let mut block = vec![0; C::BLOCK_SIZE];
+            rng.fill_bytes(&mut block);
+            let ciphertext = reference.encrypt(&block);
+
+            let mut encrypted = block.clone();
+            tables.encrypt_block(&mut encrypted);
+            assert_eq!(encrypted, ciphertext, "key {key:02x?}");
+            assert_eq!(reference.decrypt(&ciphertext), block);
+            tables.decrypt_block(&mut encrypted);
+            assert_eq!(encrypted, block);
+        }
+    }
+}
+
+#[test]
+fn reference_cipher_matches_256() {
+    compare::<Cipher256>(256, 16, 16);
+}
+
+#[test]
+fn reference_cipher_matches_384() {
+    compare::<Cipher384>(384, 16, 16);
+}
+
+#[test]
+fn reference_cipher_matches_512() {
+    compare::<Cipher512>(512, 16, 16);
+}
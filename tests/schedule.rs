@@ -0,0 +1,187 @@
+use std::mem::{MaybeUninit, size_of};
+use std::thread;
+
+use narrowway::rng::SplitMix64;
+use narrowway::{
+    BlockCipher, BlockDecrypt, BlockEncrypt, Cipher256, Cipher384, Cipher512, Decryptor256,
+    Decryptor384, Decryptor512, Encryptor256, Encryptor384, Encryptor512, KeyInit,
+};
+
+macro_rules! schedule_tests {
+    ($name: ident, $cipher: ident, $size: expr) => {
+        #[test]
+        fn $name() {
+            let mut rng = SplitMix64::new($size);
+
+            let mut keys = vec![[0; $size], [0xff; $size], [1; $size]];
+            for _ in 0..2 {
+                let mut key = [0; $size];
+                rng.fill_bytes(&mut key);
+                keys.push(key);
+            }
+
+            for key in keys {
+                let cipher = $cipher::new(key);
+                let round_keys = $cipher::expand_key(key);
+                assert_eq!(cipher.round_keys(), &round_keys);
+
+                for (round, round_key) in round_keys.iter().enumerate() {
+                    assert_eq!(
+                        $cipher::recover_key(*round_key, round),
+                        key,
+                        "round {round}"
+                    );
+                }
+
+                let mut block = [0; $size];
+                rng.fill_bytes(&mut block);
+                let ciphertext = cipher.encrypt(block);
+                let last = round_keys[$cipher::ROUNDS - 1];
+                assert_eq!(
+                    $cipher::decrypt_from_last_round_key(last, ciphertext),
+                    block
+                );
+            }
+        }
+    };
+}
+
+schedule_tests!(schedule_256, Cipher256, 32);
+schedule_tests!(schedule_384, Cipher384, 48);
+schedule_tests!(schedule_512, Cipher512, 64);
+
+#[test]
+fn digests_key_the_s_boxes() {
+    let cipher = Cipher256::new([7; 32]);
+    assert_eq!(Cipher256::ROUNDS, 16);
+    assert_eq!(Cipher384::ROUNDS, 18);
+    assert_eq!(Cipher512::ROUNDS, 20);
+
+    /* a digest is a product of non-zero field elements */
+    assert!(cipher.digests().iter().flatten().all(|&d| d != 0));
+}
+
+#[test]
+#[should_panic(expected = "only 16 rounds")]
+fn recover_key_rejects_out_of_range_round() {
+    Cipher256::recover_key([0; 32], 16);
+}
+
+macro_rules! construction_tests {
+    ($name: ident, $cipher: ident, $size: expr) => {
+        #[test]
+        fn $name() {
+            let mut rng = SplitMix64::new($size + 1);
+            let mut key = [0; $size];
+            let mut block = [0; $size];
+            rng.fill_bytes(&mut key);
+            rng.fill_bytes(&mut block);
+
+            let cipher = $cipher::new(key);
+            let boxed = $cipher::new_boxed(key);
+            let mut slot = MaybeUninit::uninit();
+            let in_place = $cipher::init_in_place(&mut slot, key);
+            let mut rekeyed = $cipher::new_boxed([0xa5; $size]);
+            rekeyed.set_key(key);
+
+            for other in [&*boxed, &*in_place, &*rekeyed] {
+                assert_eq!(other.round_keys(), cipher.round_keys());
+                assert_eq!(other.digests(), cipher.digests());
+                assert_eq!(other.encrypt(block), cipher.encrypt(block));
+                assert_eq!(other.decrypt(block), cipher.decrypt(block));
+            }
+        }
+    };
+}
+
+construction_tests!(construction_256, Cipher256, 32);
+construction_tests!(construction_384, Cipher384, 48);
+construction_tests!(construction_512, Cipher512, 64);
+
+macro_rules! one_direction_tests {
+    ($name: ident, $cipher: ident, $encryptor: ident, $decryptor: ident, $size: expr) => {
+        #[test]
+        fn $name() {
+            let mut rng = SplitMix64::new($size + 2);
+
+            for _ in 0..4 {
+                let mut key = [0; $size];
+                let mut block = [0; $size];
+                rng.fill_bytes(&mut key);
+                rng.fill_bytes(&mut block);
+
+                let cipher = $cipher::new(key);
+                let ciphertext = cipher.encrypt(block);
+
+                let mut slot = MaybeUninit::uninit();
+                let mut rekeyed = $encryptor::new_boxed([0xa5; $size]);
+                rekeyed.set_key(key);
+                let encryptors = [
+                    &$encryptor::new(key),
+                    &*$encryptor::new_boxed(key),
+                    &*$encryptor::init_in_place(&mut slot, key),
+                    &*rekeyed,
+                    &cipher.encryptor(),
+                ];
+                for encryptor in encryptors {
+                    assert_eq!(encryptor.round_keys(), cipher.round_keys());
+                    assert_eq!(encryptor.digests(), cipher.digests());
+                    assert_eq!(encryptor.encrypt(block), ciphertext);
+                }
+
+                let mut slot = MaybeUninit::uninit();
+                let mut rekeyed = $decryptor::new_boxed([0xa5; $size]);
+                rekeyed.set_key(key);
+                let decryptors = [
+                    &$decryptor::new(key),
+                    &*$decryptor::new_boxed(key),
+                    &*$decryptor::init_in_place(&mut slot, key),
+                    &*rekeyed,
+                ];
+                for decryptor in decryptors {
+                    assert_eq!(decryptor.round_keys(), cipher.round_keys());
+                    assert_eq!(decryptor.digests(), cipher.digests());
+                    assert_eq!(decryptor.decrypt(ciphertext), block);
+                }
+
+                let mut slice = block.to_vec();
+                <$cipher as BlockCipher>::Encryptor::new_from_slice(&key).encrypt_block(&mut slice);
+                assert_eq!(slice, ciphertext);
+                <$cipher as BlockCipher>::Decryptor::new_from_slice(&key).decrypt_block(&mut slice);
+                assert_eq!(slice, block);
+            }
+
+            /* exactly one S-Box per row and round is left out */
+            let s_boxes = $cipher::ROUNDS * $size / 8 * 256;
+            assert_eq!(size_of::<$cipher>() - size_of::<$encryptor>(), s_boxes);
+            assert_eq!(size_of::<$encryptor>(), size_of::<$decryptor>());
+        }
+    };
+}
+
+one_direction_tests!(one_direction_256, Cipher256, Encryptor256, Decryptor256, 32);
+one_direction_tests!(one_direction_384, Cipher384, Encryptor384, Decryptor384, 48);
+one_direction_tests!(one_direction_512, Cipher512, Encryptor512, Decryptor512, 64);
+
+#[test]
+fn new_boxed_fits_a_small_stack() {
+    /* Cipher512 alone is about 80 KiB */
+    let ciphertext = thread::Builder::new()
+        .stack_size(64 * 1024)
+        .spawn(|| {
+            let ciphertext = Cipher512::new_boxed([3; 64]).encrypt([0; 64]);
+            assert_eq!(
+                Encryptor512::new_boxed([3; 64]).encrypt([0; 64]),
+                ciphertext
+            );
+            assert_eq!(
+                Decryptor512::new_boxed([3; 64]).decrypt(ciphertext),
+                [0; 64]
+            );
+            ciphertext
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(ciphertext, Cipher512::new([3; 64]).encrypt([0; 64]));
+}
@@ -0,0 +1,23 @@
+#![cfg(feature = "self-test")]
+
+use narrowway::selftest::{self, Status};
+use narrowway::{Cipher256, Cipher512};
+
+#[test]
+fn self_test_passes() {
+    assert_eq!(selftest::run(), Ok(()));
+    assert_eq!(selftest::status(), Status::Passed);
+    assert_eq!(selftest::check_integrity(), Ok(()));
+
+    assert!(
+        Cipher256::new_checked([0x5a; 32]).is_err_and(|err| !err.to_string().contains("self-test"))
+    );
+    let _ = Cipher512::new([1; 64]);
+    assert_eq!(selftest::status(), Status::Passed);
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(selftest::crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(selftest::crc32(b""), 0);
+}
@@ -0,0 +1,23 @@
+#![cfg(feature = "self-test")]
+
+/* A test binary of its own, so no other test runs the self-test first */
+
+use std::thread;
+
+use narrowway::Cipher512;
+use narrowway::selftest::{self, Status};
+
+#[test]
+fn first_new_boxed_fits_a_small_stack() {
+    assert_eq!(selftest::status(), Status::NotRun);
+
+    let ciphertext = thread::Builder::new()
+        .stack_size(64 * 1024)
+        .spawn(|| Cipher512::new_boxed([3; 64]).encrypt([0; 64]))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(selftest::status(), Status::Passed);
+    assert_eq!(ciphertext, Cipher512::new([3; 64]).encrypt([0; 64]));
+}
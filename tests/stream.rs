@@ -0,0 +1,126 @@
+use narrowway::stream::{DecryptReader, EncryptWriter};
+use narrowway::{BlockCipher, Cipher256, Cipher384, Mode};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + 3) as u8).collect()
+}
+
+fn encrypt<C: BlockCipher>(key: &[u8], mode: Mode, iv: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut writer = EncryptWriter::new(C::new_from_slice(key), mode, iv, Vec::new());
+    /* feed in uneven pieces to exercise buffering */
+    for piece in plaintext.chunks(13) {
+        writer.write_all(piece).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn decrypt<C: BlockCipher>(
+    key: &[u8],
+    mode: Mode,
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = DecryptReader::new(C::new_from_slice(key), mode, iv, ciphertext);
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+#[test]
+fn round_trip_all_modes() {
+    let key = [7; 48];
+    let iv = [9; 48];
+
+    for mode in Mode::ALL {
+        for len in [0, 1, 47, 48, 49, 96, 200] {
+            let plaintext = data(len);
+            let ciphertext = encrypt::<Cipher384>(&key, mode, &iv, &plaintext);
+
+            let expected_len = match mode {
+                Mode::Cbc => (len / 48 + 1) * 48,
+                Mode::Ctr => len,
+                Mode::CtrCmac => len + 48,
+            };
+            assert_eq!(ciphertext.len(), expected_len, "{mode:?} {len}");
+            assert_eq!(
+                decrypt::<Cipher384>(&key, mode, &iv, &ciphertext).unwrap(),
+                plaintext
+            );
+        }
+    }
+}
+
+#[test]
+fn ctr_matches_block_encryption() {
+    let cipher = Cipher256::new([1; 32]);
+    let mut iv = [0; 32];
+    iv[31] = 0xff;
+    let plaintext = data(64);
+    let ciphertext = encrypt::<Cipher256>(&[1; 32], Mode::Ctr, &iv, &plaintext);
+
+    let mut second = iv;
+    second[30] = 1;
+    second[31] = 0;
+    for (i, counter) in [iv, second].into_iter().enumerate() {
+        let keystream = cipher.encrypt(counter);
+        for j in 0..32 {
+            assert_eq!(ciphertext[32 * i + j], plaintext[32 * i + j] ^ keystream[j]);
+        }
+    }
+}
+
+#[test]
+fn tampering_is_detected() {
+    let key = [3; 32];
+    let iv = [4; 32];
+    let mut ciphertext = encrypt::<Cipher256>(&key, Mode::CtrCmac, &iv, &data(100));
+
+    ciphertext[10] ^= 1;
+    let err = decrypt::<Cipher256>(&key, Mode::CtrCmac, &iv, &ciphertext).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    ciphertext[10] ^= 1;
+    ciphertext.truncate(ciphertext.len() - 1);
+    assert!(decrypt::<Cipher256>(&key, Mode::CtrCmac, &iv, &ciphertext).is_err());
+}
+
+#[test]
+fn cbc_rejects_bad_length_and_padding() {
+    let key = [5; 32];
+    let iv = [6; 32];
+    let ciphertext = encrypt::<Cipher256>(&key, Mode::Cbc, &iv, &data(40));
+
+    assert!(decrypt::<Cipher256>(&key, Mode::Cbc, &iv, &ciphertext[..40]).is_err());
+    assert!(decrypt::<Cipher256>(&[6; 32], Mode::Cbc, &iv, &ciphertext).is_err());
+}
+
+#[test]
+fn ctr_reader_seeks() {
+    let key = [8; 32];
+    let iv = [2; 32];
+    let plaintext = data(300);
+    let ciphertext = encrypt::<Cipher256>(&key, Mode::Ctr, &iv, &plaintext);
+
+    /* ciphertext behind a header, as in a container */
+    let mut file = b"header".to_vec();
+    file.extend_from_slice(&ciphertext);
+    let mut cursor = Cursor::new(file);
+    cursor.seek(SeekFrom::Start(6)).unwrap();
+
+    let mut reader = DecryptReader::new(Cipher256::new(key), Mode::Ctr, &iv, cursor);
+    let mut buf = [0; 20];
+
+    for pos in [100, 31, 0, 250, 64] {
+        assert_eq!(reader.seek(SeekFrom::Start(pos)).unwrap(), pos);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, plaintext[pos as usize..pos as usize + 20]);
+    }
+
+    assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 290);
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, plaintext[290..]);
+
+    assert_eq!(reader.seek(SeekFrom::Current(-50)).unwrap(), 250);
+}
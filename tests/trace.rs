@@ -0,0 +1,84 @@
+use narrowway::trace::{Listing, Observer, Step};
+use narrowway::{Cipher256, Cipher512};
+
+#[derive(Default)]
+struct Recorder {
+    keys: Vec<(usize, Vec<u8>, Vec<u8>)>,
+    steps: Vec<(usize, Step, Vec<[u8; 8]>)>,
+    output: Vec<[u8; 8]>,
+}
+
+impl Observer for Recorder {
+    fn round_key(&mut self, round: usize, key: &[u8], digests: &[u8]) {
+        self.keys.push((round, key.to_vec(), digests.to_vec()));
+    }
+    fn step(&mut self, round: usize, step: Step, state: &[[u8; 8]]) {
+        self.steps.push((round, step, state.to_vec()));
+    }
+    fn end(&mut self, state: &[[u8; 8]]) {
+        self.output = state.to_vec();
+    }
+}
+
+fn flatten(state: &[[u8; 8]]) -> Vec<u8> {
+    state.concat()
+}
+
+#[test]
+fn traced_matches_untraced() {
+    let cipher = Cipher256::new(core::array::from_fn(|i| i as u8));
+    let block = core::array::from_fn(|i| (i * 7) as u8);
+    let mut recorder = Recorder::default();
+
+    let ciphertext = cipher.encrypt_traced(block, &mut recorder);
+    assert_eq!(ciphertext, cipher.encrypt(block));
+    assert_eq!(flatten(&recorder.output), ciphertext);
+    assert_eq!(recorder.keys.len(), 16);
+    assert_eq!(recorder.steps.len(), 48);
+    assert_eq!(flatten(&recorder.steps[47].2), ciphertext);
+
+    for (round, key, digests) in &recorder.keys {
+        assert_eq!(key.len(), 32);
+        assert_eq!(digests.len(), 4);
+        assert!(digests.iter().all(|&d| d != 0), "round {round}");
+    }
+}
+
+#[test]
+fn decryption_retraces_encryption() {
+    let cipher = Cipher256::new([0x5a; 32]);
+    let block = [0x33; 32];
+    let mut forward = Recorder::default();
+    let mut backward = Recorder::default();
+
+    let ciphertext = cipher.encrypt_traced(block, &mut forward);
+    assert_eq!(cipher.decrypt_traced(ciphertext, &mut backward), block);
+
+    /* undoing a step yields the state before it, which is the previous forward step */
+    for (i, (round, step, state)) in backward.steps[..47].iter().enumerate() {
+        let before = &forward.steps[46 - i].2;
+        assert_eq!(flatten(state), flatten(before), "round {round} {step:?}");
+    }
+}
+
+#[test]
+fn listing_layout() {
+    let cipher = Cipher512::new([0; 64]);
+    let mut listing = Listing::new();
+    let ciphertext = cipher.encrypt_traced([0; 64], &mut listing);
+    let text = listing.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "NarrowWay-512 ENCRYPT");
+    assert!(lines[1].starts_with("         .input   0000000000000000 "));
+    assert!(lines[2].starts_with("round[ 0].k_sch   "));
+    assert!(lines[3].starts_with("round[ 0].digest  "));
+    assert!(lines[4].starts_with("round[ 0].s_col   "));
+    assert!(lines[5].starts_with("round[ 0].s_box   "));
+    assert!(lines[6].starts_with("round[ 0].k_app   "));
+    assert_eq!(lines.len(), 2 + 20 * 5 + 1);
+
+    let output = lines.last().unwrap();
+    let hex: String = ciphertext.iter().map(|b| format!("{b:02x}")).collect();
+    assert_eq!(output.split_whitespace().skip(1).collect::<String>(), hex);
+}